@@ -5,13 +5,13 @@ use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::hash::Hash;
 use std::ops::Deref;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tokio::sync::Mutex as TokioMutex;
 
 use crate::cli::args::{BackendArg, ToolVersionType};
 use crate::cmd::CmdLineRunner;
-use crate::config::{Config, SETTINGS};
+use crate::config::{Config, SETTINGS, Settings};
 use crate::file::{display_path, remove_all, remove_all_with_warning};
 use crate::install_context::InstallContext;
 use crate::plugins::core::CORE_PLUGINS;
@@ -19,13 +19,15 @@ use crate::plugins::{PluginType, VERSION_REGEX};
 use crate::registry::{REGISTRY, tool_enabled};
 use crate::runtime_symlinks::is_runtime_symlink;
 use crate::toolset::outdated_info::OutdatedInfo;
-use crate::toolset::{ToolRequest, ToolVersion, Toolset, install_state, is_outdated_version};
+use crate::toolset::{
+    ToolRequest, ToolVersion, ToolVersionOptions, Toolset, install_state, is_outdated_version,
+};
 use crate::ui::progress_report::SingleReport;
 use crate::{
     cache::{CacheManager, CacheManagerBuilder},
     plugins::PluginEnum,
 };
-use crate::{dirs, env, file, hash, lock_file, plugins, versions_host};
+use crate::{dirs, env, file, hash, http, integrity, lock_file, plugins, versions_host};
 use async_trait::async_trait;
 use backend_type::BackendType;
 use console::style;
@@ -339,11 +341,18 @@ pub trait Backend: Debug + Send + Sync {
                 if matches.is_empty() && query == "latest" {
                     matches = self.list_remote_versions().await?;
                 }
+                // a yanked version is only used when it was asked for exactly
+                matches.retain(|v| v == &query || !self.is_version_yanked(v));
                 Ok(find_match_in_list(&matches, &query))
             }
             None => self.latest_stable_version().await,
         }
     }
+    /// whether `version` was withdrawn after release, these are skipped when resolving `latest`
+    /// or a prefix
+    fn is_version_yanked(&self, _version: &str) -> bool {
+        false
+    }
     fn latest_installed_version(&self, query: Option<String>) -> eyre::Result<Option<String>> {
         match query {
             Some(query) => {
@@ -423,25 +432,51 @@ pub trait Backend: Debug + Send + Sync {
             plugin.is_installed_err()?;
         }
         let config = Config::try_get().await?;
-        if self.is_version_installed(&config, &tv, true) {
-            if ctx.force {
-                self.uninstall_version(&tv, &ctx.pr, false).await?;
-            } else {
-                return Ok(tv);
+        let artifact = match integrity::manifest()? {
+            Some(manifest) if ctx.force || !self.is_version_installed(&config, &tv, true) => {
+                Some(manifest.ensure_listed(&tv)?.clone())
             }
+            _ => None,
+        };
+        if !ctx.force && self.is_version_installed(&config, &tv, true) {
+            return Ok(tv);
         }
         ctx.pr.set_message("install".into());
-        let _lock = lock_file::get(&tv.install_path(), ctx.force)?;
-        self.create_install_dirs(&tv)?;
-
+        // serializes installs of this version across concurrent mise processes
+        let _lock = lock_file::get_async(
+            &tv.install_path(),
+            ctx.force,
+            Settings::get().install_lock_timeout(),
+        )
+        .await
+        .wrap_err_with(|| format!("failed to acquire install lock for {tv}"))?;
+        if !ctx.force && self.is_version_installed(&config, &tv, true) {
+            // another process installed it while this one waited for the lock
+            return Ok(tv);
+        }
+        // only once it is certain to reinstall and holding the lock, from here on every error
+        // has to keep the backup or the preserved files are gone
+        let preserved = PreservedPaths::backup(&tv.install_path(), self.preserved_paths(&tv))?;
         let old_tv = tv.clone();
-        let tv = match self.install_version_(&ctx, tv).await {
+        let installed = async {
+            if self.is_version_installed(&config, &tv, true) {
+                self.uninstall_version(&tv, &ctx.pr, false).await?;
+            }
+            self.create_install_dirs(&tv)?;
+            integrity::scope(&old_tv, artifact, self.install_version_(&ctx, tv))
+                .await
+                .inspect_err(|_| {
+                    self.cleanup_install_dirs_on_error(&old_tv);
+                })
+        };
+        let tv = match installed.await {
             Ok(tv) => tv,
             Err(e) => {
-                self.cleanup_install_dirs_on_error(&old_tv);
+                preserved.keep();
                 return Err(e);
             }
         };
+        preserved.restore(&tv.install_path())?;
 
         if tv.install_path().starts_with(*dirs::INSTALLS) {
             // this will be false only for `install-into`
@@ -461,6 +496,7 @@ pub trait Backend: Debug + Send + Sync {
         if let Err(err) = file::remove_file(self.incomplete_file_path(&tv)) {
             debug!("error removing incomplete file: {:?}", err);
         }
+        install_extras_if_enabled(self, &Settings::get(), &ctx, &tv).await;
         if let Some(script) = tv.request.options().get("postinstall") {
             ctx.pr
                 .finish_with_message("running custom postinstall hook".to_string());
@@ -488,6 +524,35 @@ pub trait Backend: Debug + Send + Sync {
         Ok(())
     }
     async fn install_version_(&self, ctx: &InstallContext, tv: ToolVersion) -> Result<ToolVersion>;
+    /// options every tool of this backend gets unless the user sets them, e.g. a `matching`
+    /// pattern that suits most tools
+    fn default_options(&self) -> ToolVersionOptions {
+        ToolVersionOptions::default()
+    }
+    /// paths relative to the install path that survive a reinstall, e.g. user data a tool keeps
+    /// next to its binaries. defaults to the comma-separated `preserve` tool option
+    fn preserved_paths(&self, tv: &ToolVersion) -> Vec<PathBuf> {
+        tv.request
+            .options()
+            .get("preserve")
+            .map(|p| {
+                p.split(',')
+                    .map(|p| p.trim())
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    /// installs shell completions that ship with the tool into [Backend::completions_dir],
+    /// only called when `install_completions` is enabled
+    async fn install_extras(&self, _ctx: &InstallContext, _tv: &ToolVersion) -> Result<()> {
+        Ok(())
+    }
+    /// where completions for `shell` (bash, zsh, fish) should be placed
+    fn completions_dir(&self, shell: &str) -> PathBuf {
+        dirs::COMPLETIONS.join(shell)
+    }
     async fn uninstall_version(
         &self,
         tv: &ToolVersion,
@@ -616,7 +681,7 @@ pub trait Backend: Debug + Send + Sync {
             .await?
             .filter_by_tool(dependencies)
             .into();
-        ts.resolve().await?;
+        ts.resolve(false).await?;
         Ok(ts)
     }
 
@@ -706,6 +771,97 @@ pub trait Backend: Debug + Send + Sync {
     async fn outdated_info(&self, _tv: &ToolVersion, _bump: bool) -> Result<Option<OutdatedInfo>> {
         Ok(None)
     }
+
+    /// a rough upper bound of the bytes installing `tv` takes on disk, checked against the free
+    /// space before anything is downloaded. `None` skips the check.
+    async fn estimated_install_size(&self, _tv: &ToolVersion) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// whether a failed install is worth retrying, by default only network-ish errors are
+    fn is_retriable_error(&self, err: &eyre::Report) -> bool {
+        http::is_transient_error(err)
+    }
+}
+
+/// lets the backend place completions after an install if the user asked for them. the version
+/// is already installed and marked complete by then, so a failure is only a warning
+async fn install_extras_if_enabled<B: Backend + ?Sized>(
+    backend: &B,
+    settings: &Settings,
+    ctx: &InstallContext,
+    tv: &ToolVersion,
+) {
+    if !settings.install_completions {
+        return;
+    }
+    if let Err(err) = backend.install_extras(ctx, tv).await {
+        warn!("failed to install completions for {tv}: {err:#}");
+    }
+}
+
+/// preserved paths moved out of an install dir while a version is reinstalled
+struct PreservedPaths {
+    dir: Option<tempfile::TempDir>,
+    paths: Vec<PathBuf>,
+}
+
+impl PreservedPaths {
+    fn backup(install_path: &Path, paths: Vec<PathBuf>) -> Result<Self> {
+        let paths = paths
+            .into_iter()
+            .filter(|p| {
+                let relative = p.components().all(|c| matches!(c, Component::Normal(_)));
+                if !relative {
+                    warn!(
+                        "not preserving {}, it must be inside the install path",
+                        p.display()
+                    );
+                }
+                relative && install_path.join(p).exists()
+            })
+            .collect_vec();
+        let Some(parent) = install_path.parent().filter(|_| !paths.is_empty()) else {
+            return Ok(Self { dir: None, paths });
+        };
+        // a sibling of the install path so the moves are renames on the same filesystem
+        let dir = tempfile::Builder::new()
+            .prefix(".mise-preserve-")
+            .tempdir_in(parent)?;
+        for p in &paths {
+            let to = dir.path().join(p);
+            file::create_dir_all(to.parent().unwrap())?;
+            file::rename(install_path.join(p), to)?;
+        }
+        Ok(Self {
+            dir: Some(dir),
+            paths,
+        })
+    }
+
+    fn restore(self, install_path: &Path) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let restored = self.paths.iter().try_for_each(|p| {
+            let to = install_path.join(p);
+            remove_all(&to)?;
+            file::create_dir_all(to.parent().unwrap())?;
+            file::rename(dir.path().join(p), to)
+        });
+        if restored.is_err() {
+            self.keep();
+        }
+        restored
+    }
+
+    /// leaves the preserved paths on disk when the install failed so nothing is lost
+    fn keep(self) {
+        if let Some(dir) = self.dir {
+            let dir = dir.keep();
+            warn!("preserved files were kept in {}", display_path(&dir));
+        }
+    }
 }
 
 fn find_match_in_list(list: &[String], query: &str) -> Option<String> {
@@ -771,3 +927,283 @@ impl Ord for dyn Backend {
 pub fn reset() {
     *TOOLS.lock().unwrap() = None;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toolset::ToolSource;
+    use crate::ui::progress_report::QuietReport;
+
+    #[derive(Debug)]
+    struct CompletionsBackend {
+        ba: Arc<BackendArg>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Backend for CompletionsBackend {
+        fn ba(&self) -> &Arc<BackendArg> {
+            &self.ba
+        }
+
+        async fn _list_remote_versions(&self) -> Result<Vec<String>> {
+            Ok(vec!["1.0.0".into()])
+        }
+
+        async fn install_version_(
+            &self,
+            _ctx: &InstallContext,
+            tv: ToolVersion,
+        ) -> Result<ToolVersion> {
+            Ok(tv)
+        }
+
+        async fn install_extras(&self, _ctx: &InstallContext, _tv: &ToolVersion) -> Result<()> {
+            if self.fail {
+                bail!("no completions");
+            }
+            let dir = self.completions_dir("zsh");
+            file::create_dir_all(&dir)?;
+            file::write(dir.join("_completions-test"), "#compdef foo\n")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_extras_if_enabled() {
+        let ba = Arc::new(BackendArg::new("completions-test".into(), None));
+        let backend = CompletionsBackend {
+            ba: ba.clone(),
+            fail: false,
+        };
+        let tr = ToolRequest::new(ba.clone(), "1.0.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "1.0.0".into());
+        let ctx = InstallContext {
+            ts: Arc::new(Toolset::default()),
+            pr: Box::new(QuietReport::new()),
+            force: false,
+            sbom: Default::default(),
+        };
+        let completion = dirs::COMPLETIONS.join("zsh/_completions-test");
+        let _ = file::remove_file(&completion);
+
+        let mut settings = Settings::get().deref().clone();
+        settings.install_completions = false;
+        install_extras_if_enabled(&backend, &settings, &ctx, &tv).await;
+        assert!(!completion.exists());
+
+        settings.install_completions = true;
+        install_extras_if_enabled(&backend, &settings, &ctx, &tv).await;
+        assert!(completion.exists());
+        file::remove_file(&completion).unwrap();
+
+        // a failure doesn't fail the install that already finished
+        let failing = CompletionsBackend { ba, fail: true };
+        install_extras_if_enabled(&failing, &settings, &ctx, &tv).await;
+        assert!(!completion.exists());
+    }
+
+    #[derive(Debug)]
+    struct PreserveBackend {
+        ba: Arc<BackendArg>,
+    }
+
+    #[async_trait]
+    impl Backend for PreserveBackend {
+        fn ba(&self) -> &Arc<BackendArg> {
+            &self.ba
+        }
+
+        async fn _list_remote_versions(&self) -> Result<Vec<String>> {
+            Ok(vec!["1.0.0".into()])
+        }
+
+        async fn install_version_(
+            &self,
+            _ctx: &InstallContext,
+            tv: ToolVersion,
+        ) -> Result<ToolVersion> {
+            file::create_dir_all(tv.install_path().join("bin"))?;
+            file::write(tv.install_path().join("bin/preserve-test"), "")?;
+            Ok(tv)
+        }
+
+        fn preserved_paths(&self, _tv: &ToolVersion) -> Vec<PathBuf> {
+            vec!["data".into(), "../escape".into()]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preserved_paths_survive_reinstall() {
+        let ba = Arc::new(BackendArg::new("preserve-test".into(), None));
+        let backend = PreserveBackend { ba: ba.clone() };
+        let tr = ToolRequest::new(ba, "1.0.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "1.0.0".into());
+        let ctx = || InstallContext {
+            ts: Arc::new(Toolset::default()),
+            pr: Box::new(QuietReport::new()),
+            force: true,
+            sbom: Default::default(),
+        };
+        let install_path = tv.install_path();
+        let _ = file::remove_all(&install_path);
+        backend.install_version(ctx(), tv.clone()).await.unwrap();
+        file::create_dir_all(install_path.join("data")).unwrap();
+        file::write(install_path.join("data/notes.txt"), "keep me").unwrap();
+        file::write(install_path.join("scratch.txt"), "drop me").unwrap();
+
+        backend.install_version(ctx(), tv).await.unwrap();
+        assert_eq!(
+            file::read_to_string(install_path.join("data/notes.txt")).unwrap(),
+            "keep me"
+        );
+        assert!(!install_path.join("scratch.txt").exists());
+        assert!(install_path.join("bin/preserve-test").exists());
+        let leftovers = file::ls(install_path.parent().unwrap())
+            .unwrap()
+            .into_iter()
+            .filter(|p| p.to_string_lossy().contains(".mise-preserve-"))
+            .count();
+        assert_eq!(leftovers, 0);
+        file::remove_all(&install_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_preserved_paths_kept_when_already_installed() {
+        let ba = Arc::new(BackendArg::new("preserve-test".into(), None));
+        let backend = PreserveBackend { ba: ba.clone() };
+        let tr = ToolRequest::new(ba, "1.1.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "1.1.0".into());
+        let ctx = || InstallContext {
+            ts: Arc::new(Toolset::default()),
+            pr: Box::new(QuietReport::new()),
+            force: false,
+            sbom: Default::default(),
+        };
+        let install_path = tv.install_path();
+        let _ = file::remove_all(&install_path);
+        backend.install_version(ctx(), tv.clone()).await.unwrap();
+        file::create_dir_all(install_path.join("data")).unwrap();
+        file::write(install_path.join("data/notes.txt"), "keep me").unwrap();
+
+        // already installed so nothing is reinstalled and nothing is backed up
+        backend.install_version(ctx(), tv).await.unwrap();
+        assert_eq!(
+            file::read_to_string(install_path.join("data/notes.txt")).unwrap(),
+            "keep me"
+        );
+        let leftovers = file::ls(install_path.parent().unwrap())
+            .unwrap()
+            .into_iter()
+            .filter(|p| p.to_string_lossy().contains(".mise-preserve-"))
+            .count();
+        assert_eq!(leftovers, 0);
+        file::remove_all(&install_path).unwrap();
+    }
+
+    #[derive(Debug)]
+    struct SlowBackend {
+        ba: Arc<BackendArg>,
+        installs: std::sync::atomic::AtomicUsize,
+        running: std::sync::atomic::AtomicUsize,
+        max_running: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Backend for SlowBackend {
+        fn ba(&self) -> &Arc<BackendArg> {
+            &self.ba
+        }
+
+        async fn _list_remote_versions(&self) -> Result<Vec<String>> {
+            Ok(vec!["1.0.0".into()])
+        }
+
+        async fn install_version_(
+            &self,
+            _ctx: &InstallContext,
+            tv: ToolVersion,
+        ) -> Result<ToolVersion> {
+            use std::sync::atomic::Ordering::SeqCst;
+            self.installs.fetch_add(1, SeqCst);
+            let running = self.running.fetch_add(1, SeqCst) + 1;
+            self.max_running.fetch_max(running, SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            file::write(tv.install_path().join("slow-test"), "")?;
+            self.running.fetch_sub(1, SeqCst);
+            Ok(tv)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_installs_serialize() {
+        use std::sync::atomic::Ordering::SeqCst;
+        let ba = Arc::new(BackendArg::new("slow-test".into(), None));
+        let backend = Arc::new(SlowBackend {
+            ba: ba.clone(),
+            installs: Default::default(),
+            running: Default::default(),
+            max_running: Default::default(),
+        });
+        let tr = ToolRequest::new(ba, "1.0.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "1.0.0".into());
+        let _ = file::remove_all(tv.install_path());
+        let install = |backend: Arc<SlowBackend>, tv: ToolVersion| {
+            tokio::spawn(async move {
+                let ctx = InstallContext {
+                    ts: Arc::new(Toolset::default()),
+                    pr: Box::new(QuietReport::new()),
+                    force: false,
+                    sbom: Default::default(),
+                };
+                backend.install_version(ctx, tv).await
+            })
+        };
+        let a = install(backend.clone(), tv.clone());
+        let b = install(backend.clone(), tv.clone());
+        a.await.unwrap().unwrap();
+        b.await.unwrap().unwrap();
+        // the second waited for the lock and then found the version installed
+        assert_eq!(backend.installs.load(SeqCst), 1);
+        assert_eq!(backend.max_running.load(SeqCst), 1);
+        file::remove_all(tv.install_path()).unwrap();
+    }
+
+    #[derive(Debug)]
+    struct YankedBackend {
+        ba: Arc<BackendArg>,
+    }
+
+    #[async_trait]
+    impl Backend for YankedBackend {
+        fn ba(&self) -> &Arc<BackendArg> {
+            &self.ba
+        }
+
+        async fn _list_remote_versions(&self) -> Result<Vec<String>> {
+            Ok(vec!["1.0.0".into(), "1.1.0".into(), "1.2.0".into()])
+        }
+
+        async fn install_version_(
+            &self,
+            _ctx: &InstallContext,
+            tv: ToolVersion,
+        ) -> Result<ToolVersion> {
+            Ok(tv)
+        }
+
+        fn is_version_yanked(&self, version: &str) -> bool {
+            version == "1.2.0"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latest_version_skips_yanked() {
+        let backend = YankedBackend {
+            ba: Arc::new(BackendArg::new("yanked-test".into(), None)),
+        };
+        let latest = |q: &str| backend.latest_version(Some(q.into()));
+        assert_eq!(latest("latest").await.unwrap(), Some("1.1.0".into()));
+        assert_eq!(latest("1").await.unwrap(), Some("1.1.0".into()));
+        assert_eq!(latest("1.2.0").await.unwrap(), Some("1.2.0".into()));
+    }
+}
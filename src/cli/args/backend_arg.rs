@@ -56,10 +56,11 @@ impl BackendArg {
             .unwrap_or(&short)
             .split_once(':')
             .unwrap_or(("", full.as_ref().unwrap_or(&short)));
-        let short = regex!(r#"\[.+\]$"#).replace_all(&short, "").to_string();
+        let short = regex!(r#"\[.*\]$"#).replace_all(&short, "").to_string();
 
         let mut opts = None;
-        if let Some(c) = regex!(r"^(.+)\[(.+)\]$").captures(tool_name) {
+        // `tool[]` sets empty options, which is different from not setting any
+        if let Some(c) = regex!(r"^(.+)\[(.*)\]$").captures(tool_name) {
             tool_name = c.get(1).unwrap().as_str();
             opts = Some(parse_tool_options(c.get(2).unwrap().as_str()));
         }
@@ -192,12 +193,15 @@ impl BackendArg {
     }
 
     pub fn opts(&self) -> ToolVersionOptions {
-        self.opts.clone().unwrap_or_else(|| {
-            if let Some(c) = regex!(r"^(.+)\[(.+)\]$").captures(&self.full()) {
-                parse_tool_options(c.get(2).unwrap().as_str())
-            } else {
-                ToolVersionOptions::default()
-            }
+        self.explicit_opts().unwrap_or_default()
+    }
+
+    /// the options if any were given, even if they are empty like `tool[]`
+    pub fn explicit_opts(&self) -> Option<ToolVersionOptions> {
+        self.opts.clone().or_else(|| {
+            regex!(r"^(.+)\[(.*)\]$")
+                .captures(&self.full())
+                .map(|c| parse_tool_options(c.get(2).unwrap().as_str()))
         })
     }
 
@@ -208,7 +212,7 @@ impl BackendArg {
     pub fn tool_name(&self) -> String {
         let full = self.full();
         let (_backend, tool_name) = full.split_once(':').unwrap_or(("", &full));
-        let tool_name = regex!(r#"\[.+\]$"#).replace_all(tool_name, "").to_string();
+        let tool_name = regex!(r#"\[.*\]$"#).replace_all(tool_name, "").to_string();
         tool_name.to_string()
     }
 
@@ -3,6 +3,7 @@ use std::str::FromStr;
 use std::{fmt::Display, sync::Arc};
 
 use crate::cli::args::BackendArg;
+use crate::toolset::tool_request::parse_ref_type;
 use crate::toolset::{ToolRequest, ToolSource};
 use crate::ui::style;
 use console::style;
@@ -59,8 +60,8 @@ impl FromStr for ToolVersionType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         trace!("parsing ToolVersionType from: {}", s);
         Ok(match s.split_once(':') {
-            Some((ref_type @ ("ref" | "tag" | "branch" | "rev"), r)) => {
-                Self::Ref(ref_type.to_string(), r.to_string())
+            Some((ref_type, r)) if parse_ref_type(ref_type).is_some() => {
+                Self::Ref(ref_type.to_lowercase(), r.to_string())
             }
             Some(("prefix", p)) => Self::Prefix(p.to_string()),
             Some(("path", p)) => Self::Path(PathBuf::from(p)),
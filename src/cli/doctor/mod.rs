@@ -398,6 +398,20 @@ impl Doctor {
             .join("\n");
 
         info::section("path", paths)?;
+
+        let config = Config::get().await;
+        let (_, env_results) = ts.final_env(&config).await?;
+        let (_, dupes) = ts.list_final_paths_with_dupes(&config, env_results).await?;
+        if dupes > 0 {
+            self.warnings.push(format!(
+                "{dupes} duplicate PATH {} added by mise, some tools share bin directories",
+                if dupes == 1 {
+                    "entry was"
+                } else {
+                    "entries were"
+                }
+            ));
+        }
         Ok(())
     }
 }
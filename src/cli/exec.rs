@@ -12,8 +12,8 @@ use crate::cli::args::ToolArg;
 #[cfg(any(test, windows))]
 use crate::cmd;
 use crate::config::{Config, SETTINGS};
-use crate::env;
 use crate::toolset::{InstallOptions, ToolsetBuilder};
+use crate::{env, hooks};
 
 /// Execute a command with tool(s) set
 ///
@@ -53,6 +53,7 @@ pub struct Exec {
 
 impl Exec {
     pub async fn run(self) -> Result<()> {
+        hooks::deny_in_env_finalize("exec")?;
         let config = Config::get().await;
         let mut ts = measure!("toolset", {
             ToolsetBuilder::new()
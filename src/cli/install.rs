@@ -48,6 +48,7 @@ pub struct Install {
 
 impl Install {
     pub async fn run(self) -> Result<()> {
+        hooks::deny_in_env_finalize("install")?;
         let config = Config::get().await;
         match &self.tool {
             Some(runtime) => {
@@ -119,7 +120,7 @@ impl Install {
                                 let tvr = ToolRequest::Version {
                                     backend: ta.ba.clone(),
                                     version: "latest".into(),
-                                    options: ta.ba.opts(),
+                                    options: ta.ba.explicit_opts(),
                                     source: ToolSource::Argument,
                                 };
                                 requests.push(tvr);
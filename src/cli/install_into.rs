@@ -46,6 +46,7 @@ impl InstallInto {
             ts: ts.clone(),
             pr: mpr.add(&tv.style()),
             force: true,
+            sbom: Default::default(),
         };
         tv.install_path = Some(self.path.clone());
         backend.install_version(install_ctx, tv).await?;
@@ -12,6 +12,7 @@ use crate::cli::args::BackendArg;
 use crate::cli::prune;
 use crate::config;
 use crate::config::Config;
+use crate::sbom;
 use crate::toolset::{ToolSource, ToolVersion, Toolset};
 use crate::ui::table::MiseTable;
 
@@ -230,7 +231,7 @@ impl Ls {
         }
 
         let mut ts = Toolset::from(trs);
-        ts.resolve().await?;
+        ts.resolve(false).await?;
 
         let rvs: Vec<RuntimeRow> = ts
             .list_all_versions()
@@ -270,6 +271,8 @@ struct JSONToolVersion {
     version: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     requested_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_version: Option<String>,
     install_path: PathBuf,
     #[serde(skip_serializing_if = "Option::is_none")]
     source: Option<IndexMap<String, String>>,
@@ -342,6 +345,7 @@ async fn json_tool_version_from(config: &Config, row: RuntimeRow<'_>) -> JSONToo
         } else {
             Some(tv.request.version())
         },
+        resolved_version: resolved_version(&tv),
         source: if source.is_unknown() {
             None
         } else {
@@ -372,18 +376,33 @@ async fn version_status_from(
         VersionStatus::Symlink(tv.version.clone(), !source.is_unknown())
     } else if !p.is_version_installed(config, tv, true) {
         VersionStatus::Missing(tv.version.clone())
-    } else if !source.is_unknown() {
-        let outdated = if ls.outdated {
-            p.is_version_outdated(tv).await
-        } else {
-            false
-        };
-        VersionStatus::Active(tv.version.clone(), outdated)
     } else {
-        VersionStatus::Inactive(tv.version.clone())
+        let version = match resolved_version(tv) {
+            Some(resolved) => format!("{} ({resolved})", tv.version),
+            None => tv.version.clone(),
+        };
+        if !source.is_unknown() {
+            let outdated = if ls.outdated {
+                p.is_version_outdated(tv).await
+            } else {
+                false
+            };
+            VersionStatus::Active(version, outdated)
+        } else {
+            VersionStatus::Inactive(version)
+        }
     }
 }
 
+/// the concrete version an installed named build (e.g. zig's `ref:mach-latest`) resolved to
+fn resolved_version(tv: &ToolVersion) -> Option<String> {
+    sbom::read_receipt(&tv.install_path())
+        .ok()
+        .flatten()
+        .and_then(|r| r.entry.resolved_version)
+        .filter(|v| v != &tv.version)
+}
+
 static AFTER_LONG_HELP: &str = color_print::cstr!(
     r#"<bold><underline>Examples:</underline></bold>
 
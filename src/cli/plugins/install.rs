@@ -84,7 +84,7 @@ impl PluginsInstall {
 
     async fn install_all_missing_plugins(self: Arc<Self>, config: &Config) -> Result<()> {
         let ts = ToolsetBuilder::new().build(config).await?;
-        let missing_plugins = ts.list_missing_plugins();
+        let missing_plugins = ts.list_missing_plugins().await;
         if missing_plugins.is_empty() {
             warn!("all plugins already installed");
         }
@@ -87,7 +87,7 @@ pub async fn prunable_tools(
 
     for cf in config.get_tracked_config_files()?.values() {
         let mut ts = Toolset::from(cf.to_tool_request_set()?);
-        ts.resolve().await?;
+        ts.resolve(false).await?;
         for (_, tv) in ts.list_current_versions() {
             to_delete.remove(&(tv.ba().short.to_string(), tv.tv_pathname()));
         }
@@ -57,7 +57,7 @@ impl Tool {
     pub async fn run(self) -> Result<()> {
         let config = Config::get().await;
         let mut ts = ToolsetBuilder::new().build(&config).await?;
-        ts.resolve().await?;
+        ts.resolve(false).await?;
         let tvl = ts.versions.get(&self.tool);
         let tv = tvl.map(|tvl| tvl.versions.first().unwrap());
         let ba = tv.map(|tv| tv.ba()).unwrap_or_else(|| &self.tool);
@@ -65,6 +65,7 @@ impl Uninstall {
             }
         }
 
+        config.get_toolset().await?.clear_installed_cache();
         file::touch_dir(&dirs::DATA)?;
         config::rebuild_shims_and_runtime_symlinks(&[]).await?;
 
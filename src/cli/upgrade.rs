@@ -8,7 +8,7 @@ use crate::toolset::outdated_info::OutdatedInfo;
 use crate::toolset::{InstallOptions, ResolveOptions, ToolVersion, ToolsetBuilder};
 use crate::ui::multi_progress_report::MultiProgressReport;
 use crate::ui::progress_report::SingleReport;
-use crate::{config, ui};
+use crate::{config, hooks, ui};
 use console::Term;
 use demand::DemandOption;
 use eyre::{Context, Result, eyre};
@@ -60,6 +60,7 @@ pub struct Upgrade {
 
 impl Upgrade {
     pub async fn run(self) -> Result<()> {
+        hooks::deny_in_env_finalize("upgrade")?;
         let config = Config::get().await;
         let ts = ToolsetBuilder::new()
             .with_args(&self.tool)
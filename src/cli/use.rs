@@ -17,7 +17,7 @@ use crate::toolset::{
     InstallOptions, ResolveOptions, ToolRequest, ToolSource, ToolVersion, ToolsetBuilder,
 };
 use crate::ui::ctrlc;
-use crate::{config, env, file};
+use crate::{config, env, file, hooks};
 
 /// Installs a tool and adds the version to mise.toml.
 ///
@@ -99,6 +99,7 @@ pub struct Use {
 
 impl Use {
     pub async fn run(mut self) -> Result<()> {
+        hooks::deny_in_env_finalize("use")?;
         if self.tool.is_empty() && self.remove.is_empty() {
             self.tool = vec![self.tool_selector()?];
         }
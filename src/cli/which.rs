@@ -27,6 +27,11 @@ pub struct Which {
     #[clap(long, conflicts_with = "plugin")]
     pub version: bool,
 
+    /// Show every active tool that provides the bin, not just the one that wins
+    /// The first one listed is the one that is used.
+    #[clap(short, long, verbatim_doc_comment)]
+    pub all: bool,
+
     /// Use a specific tool@version
     /// e.g.: `mise which npm --tool=node@20`
     #[clap(short, long, value_name = "TOOL@VERSION", verbatim_doc_comment)]
@@ -41,6 +46,22 @@ impl Which {
         let ts = self.get_toolset().await?;
 
         let bin_name = self.bin_name.clone().unwrap();
+        if self.all {
+            let found = ts.which_with_shadowed(&bin_name).await;
+            if found.is_empty() {
+                return self.not_found(&bin_name);
+            }
+            for (p, tv, path) in found {
+                if self.version {
+                    miseprintln!("{}", tv.version);
+                } else if self.plugin {
+                    miseprintln!("{p}");
+                } else {
+                    miseprintln!("{}", path.display());
+                }
+            }
+            return Ok(());
+        }
         match ts.which(&bin_name).await {
             Some((p, tv)) => {
                 if self.version {
@@ -53,15 +74,16 @@ impl Which {
                 }
                 Ok(())
             }
-            None => {
-                if self.has_shim(&bin_name) {
-                    bail!(
-                        "{bin_name} is a mise bin however it is not currently active. Use `mise use` to activate it in this directory."
-                    )
-                } else {
-                    bail!("{bin_name} is not a mise bin. Perhaps you need to install it first.",)
-                }
-            }
+            None => self.not_found(&bin_name),
+        }
+    }
+    fn not_found(&self, bin_name: &str) -> Result<()> {
+        if self.has_shim(bin_name) {
+            bail!(
+                "{bin_name} is a mise bin however it is not currently active. Use `mise use` to activate it in this directory."
+            )
+        } else {
+            bail!("{bin_name} is not a mise bin. Perhaps you need to install it first.",)
         }
     }
     async fn complete(&self) -> Result<()> {
@@ -105,5 +127,9 @@ static AFTER_LONG_HELP: &str = color_print::cstr!(
 
     $ <bold>mise which node --version</bold>
     20.0.0
+
+    $ <bold>mise which node --all --version</bold>
+    20.0.0
+    18.0.0
 "#
 );
@@ -48,6 +48,8 @@ pub struct MiseToml {
     env_file: Vec<String>,
     #[serde(default)]
     env: EnvList,
+    #[serde(default)]
+    inherit_env: Option<bool>,
     #[serde(default, deserialize_with = "deserialize_arr")]
     env_path: Vec<String>,
     #[serde(default)]
@@ -320,6 +322,10 @@ impl ConfigFile for MiseToml {
         Ok(all)
     }
 
+    fn inherit_env(&self) -> bool {
+        self.inherit_env.unwrap_or(true)
+    }
+
     fn vars_entries(&self) -> eyre::Result<Vec<EnvDirective>> {
         Ok(self.vars.0.clone())
     }
@@ -579,6 +585,9 @@ impl Debug for MiseToml {
                 d.field("env", &env);
             }
         }
+        if let Some(inherit_env) = self.inherit_env {
+            d.field("inherit_env", &inherit_env);
+        }
         if !self.alias.is_empty() {
             d.field("alias", &self.alias);
         }
@@ -601,6 +610,7 @@ impl Clone for MiseToml {
             path: self.path.clone(),
             env_file: self.env_file.clone(),
             env: self.env.clone(),
+            inherit_env: self.inherit_env,
             env_path: self.env_path.clone(),
             alias: self.alias.clone(),
             doc: Mutex::new(self.doc.lock().unwrap().clone()),
@@ -627,11 +637,7 @@ impl From<ToolRequest> for MiseTomlTool {
                 source: _source,
             } => Self {
                 tt: ToolVersionType::Version(version),
-                options: if options.is_empty() {
-                    None
-                } else {
-                    Some(options)
-                },
+                options: options.filter(|o| !o.is_empty()),
             },
             ToolRequest::Path {
                 path,
@@ -640,11 +646,7 @@ impl From<ToolRequest> for MiseTomlTool {
                 source: _source,
             } => Self {
                 tt: ToolVersionType::Path(path),
-                options: if options.is_empty() {
-                    None
-                } else {
-                    Some(options)
-                },
+                options: options.filter(|o| !o.is_empty()),
             },
             ToolRequest::Prefix {
                 prefix,
@@ -653,11 +655,7 @@ impl From<ToolRequest> for MiseTomlTool {
                 source: _source,
             } => Self {
                 tt: ToolVersionType::Prefix(prefix),
-                options: if options.is_empty() {
-                    None
-                } else {
-                    Some(options)
-                },
+                options: options.filter(|o| !o.is_empty()),
             },
             ToolRequest::Ref {
                 ref_,
@@ -667,11 +665,7 @@ impl From<ToolRequest> for MiseTomlTool {
                 source: _source,
             } => Self {
                 tt: ToolVersionType::Ref(ref_, ref_type),
-                options: if options.is_empty() {
-                    None
-                } else {
-                    Some(options)
-                },
+                options: options.filter(|o| !o.is_empty()),
             },
             ToolRequest::Sub {
                 sub,
@@ -681,11 +675,7 @@ impl From<ToolRequest> for MiseTomlTool {
                 source: _source,
             } => Self {
                 tt: ToolVersionType::Sub { sub, orig_version },
-                options: if options.is_empty() {
-                    None
-                } else {
-                    Some(options)
-                },
+                options: options.filter(|o| !o.is_empty()),
             },
             ToolRequest::System {
                 options,
@@ -693,11 +683,7 @@ impl From<ToolRequest> for MiseTomlTool {
                 source: _source,
             } => Self {
                 tt: ToolVersionType::System,
-                options: if options.is_empty() {
-                    None
-                } else {
-                    Some(options)
-                },
+                options: options.filter(|o| !o.is_empty()),
             },
         }
     }
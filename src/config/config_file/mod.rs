@@ -80,6 +80,10 @@ pub trait ConfigFile: Debug + Send + Sync {
     fn env_entries(&self) -> Result<Vec<EnvDirective>> {
         Ok(Default::default())
     }
+    /// if false, env directives from parent config files are ignored
+    fn inherit_env(&self) -> bool {
+        true
+    }
     fn vars_entries(&self) -> Result<Vec<EnvDirective>> {
         Ok(Default::default())
     }
@@ -123,7 +127,7 @@ impl dyn ConfigFile {
         let config = Config::get().await;
         // TODO: this has become a complete mess and could probably be greatly simplified
         let mut ts = self.to_toolset()?.to_owned();
-        ts.resolve().await?;
+        ts.resolve(false).await?;
         trace!("resolved toolset");
         let mut plugins_to_update = HashMap::new();
         for ta in tools {
@@ -146,7 +150,7 @@ impl dyn ConfigFile {
             ts.versions.insert(ba.clone(), tvl);
         }
         trace!("resolving toolset 2");
-        ts.resolve().await?;
+        ts.resolve(false).await?;
         trace!("resolved toolset 2");
         for (ba, versions) in plugins_to_update {
             let mut new = vec![];
@@ -5,14 +5,15 @@ use crate::env_diff::EnvMap;
 use crate::file::display_path;
 use crate::path_env::PathEnv;
 use crate::tera::{get_tera, tera_exec};
-use eyre::{Context, eyre};
-use indexmap::IndexMap;
+use eyre::{Context, bail, eyre};
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 use serde_json::Value;
 use std::cmp::PartialEq;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::path::{Path, PathBuf};
+use xx::regex;
 
 use super::Config;
 
@@ -181,6 +182,7 @@ impl EnvResults {
                 acc.push((directive.clone(), source.clone()));
                 acc
             });
+        check_env_cycles(&input)?;
         for (directive, source) in input {
             let mut tera = get_tera(source.parent());
             tera.register_function(
@@ -391,6 +393,72 @@ impl EnvResults {
     }
 }
 
+/// errors when env values reference each other in a loop, e.g. `A = "{{env.B}}"` with
+/// `B = "{{env.A}}"` below it. directives resolve in order, so a reference reads the closest
+/// definition above it and only a key that isn't defined above is taken from the one below. each
+/// definition is checked on its own so redefining a key, e.g. in another file, doesn't tie together
+/// the references of its old and new value. a value referencing its own key reads the previous value
+/// so that is allowed
+fn check_env_cycles(input: &[(EnvDirective, PathBuf)]) -> eyre::Result<()> {
+    let re = regex!(r#"\benv(?:\.([A-Za-z_][A-Za-z0-9_]*)|\[\s*["']([^"']+)["']\s*\])"#);
+    let vals = input
+        .iter()
+        .filter_map(|(directive, _)| match directive {
+            EnvDirective::Val(k, v, _) => Some((k.as_str(), v.as_str())),
+            _ => None,
+        })
+        .collect_vec();
+    // the index in `vals` of the definition each value depends on
+    let mut deps: Vec<IndexSet<usize>> = vec![IndexSet::new(); vals.len()];
+    let mut latest: HashMap<&str, usize> = HashMap::new();
+    for (i, (k, v)) in vals.iter().enumerate() {
+        if v.contains("{{") || v.contains("{%") {
+            for c in re.captures_iter(v) {
+                let dep = c.get(1).or(c.get(2)).unwrap().as_str();
+                if dep == *k {
+                    continue;
+                }
+                let def = latest
+                    .get(dep)
+                    .copied()
+                    .or_else(|| (i + 1..vals.len()).find(|j| vals[*j].0 == dep));
+                deps[i].extend(def);
+            }
+        }
+        latest.insert(k, i);
+    }
+    fn visit(
+        i: usize,
+        vals: &[(&str, &str)],
+        deps: &[IndexSet<usize>],
+        stack: &mut Vec<usize>,
+        done: &mut HashSet<usize>,
+    ) -> eyre::Result<()> {
+        if let Some(pos) = stack.iter().position(|j| *j == i) {
+            let cycle = stack[pos..]
+                .iter()
+                .chain([&i])
+                .map(|j| vals[*j].0)
+                .join(" -> ");
+            bail!("circular reference in env: {cycle}");
+        }
+        if !done.insert(i) {
+            return Ok(());
+        }
+        stack.push(i);
+        for dep in &deps[i] {
+            visit(*dep, vals, deps, stack, done)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+    let mut done = HashSet::new();
+    for i in 0..vals.len() {
+        visit(i, &vals, &deps, &mut vec![], &mut done)?;
+    }
+    Ok(())
+}
+
 impl Debug for EnvResults {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut ds = f.debug_struct("EnvResults");
@@ -415,3 +483,74 @@ impl Debug for EnvResults {
         ds.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tera::BASE_CONTEXT;
+
+    #[tokio::test]
+    async fn test_env_cycle() {
+        let config = Config::get().await;
+        let val = |k: &str, v: &str| {
+            (
+                EnvDirective::Val(k.into(), v.into(), Default::default()),
+                PathBuf::from("/config"),
+            )
+        };
+        let err = EnvResults::resolve(
+            &config,
+            BASE_CONTEXT.clone(),
+            &EnvMap::new(),
+            vec![
+                val("A", "{{ env.B }}"),
+                val("C", "c"),
+                val("B", "b-{{ env['A'] }}"),
+            ],
+            EnvResolveOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "circular reference in env: A -> B -> A");
+
+        // a value may extend its own previous value
+        let results = EnvResults::resolve(
+            &config,
+            BASE_CONTEXT.clone(),
+            &EnvMap::new(),
+            vec![
+                val("A", "a"),
+                val("A", "{{ env.A }}-a"),
+                val("B", "{{ env.A }}"),
+            ],
+            EnvResolveOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(results.env["B"].0, "a-a");
+
+        // references read the closest definition above them, so once A is defined B reads that
+        // and redefining A from B is not a loop
+        let results = EnvResults::resolve(
+            &config,
+            BASE_CONTEXT.clone(),
+            &EnvMap::new(),
+            vec![
+                val("A", "a"),
+                (
+                    EnvDirective::Val("B".into(), "{{ env.A }}".into(), Default::default()),
+                    PathBuf::from("/project/mise.toml"),
+                ),
+                (
+                    EnvDirective::Val("A".into(), "{{ env.B }}-b".into(), Default::default()),
+                    PathBuf::from("/project/mise.toml"),
+                ),
+            ],
+            EnvResolveOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(results.env["B"].0, "a");
+        assert_eq!(results.env["A"].0, "a-b");
+    }
+}
@@ -247,7 +247,7 @@ impl Config {
         self.toolset
             .get_or_try_init(|| async {
                 let mut ts = Toolset::from(self.get_tool_request_set().await?.clone());
-                ts.resolve().await?;
+                ts.resolve(false).await?;
                 Ok(ts)
             })
             .await
@@ -423,9 +423,8 @@ impl Config {
 
     async fn load_env(&self) -> Result<EnvResults> {
         time!("load_env start");
-        let entries = self
-            .config_files
-            .iter()
+        let entries = env_config_files(&self.config_files)
+            .into_iter()
             .rev()
             .map(|(source, cf)| {
                 cf.env_entries()
@@ -591,6 +590,25 @@ impl Config {
     }
 }
 
+/// config files whose env directives apply, closest first. When a config file sets
+/// `inherit_env = false` the other files of its config root (e.g. mise.toml next to a
+/// mise.local.toml, or conf.d files) still apply but parent directories' env is ignored
+pub fn env_config_files(config_files: &ConfigMap) -> Vec<(&PathBuf, &Arc<dyn ConfigFile>)> {
+    let mut files = vec![];
+    let mut stop_root = None;
+    for (path, cf) in config_files {
+        let root = cf.config_root();
+        if stop_root.as_ref().is_some_and(|r| *r != root) {
+            break;
+        }
+        if !cf.inherit_env() {
+            stop_root = Some(root);
+        }
+        files.push((path, cf));
+    }
+    files
+}
+
 fn configs_at_root<'a>(dir: &Path, config_files: &'a ConfigMap) -> Vec<&'a Arc<dyn ConfigFile>> {
     DEFAULT_CONFIG_FILENAMES
         .iter()
@@ -1437,12 +1455,74 @@ async fn load_task_file(path: &Path, config_root: &Path) -> Result<Vec<Task>> {
 #[cfg(test)]
 #[cfg(unix)]
 mod tests {
+    use indoc::indoc;
     use insta::assert_debug_snapshot;
     use std::collections::BTreeMap;
     use std::fs::{self, File};
     use tempfile::TempDir;
 
     use super::*;
+    use crate::hooks::Hooks;
+
+    #[test]
+    fn test_env_config_files_inherit_env() {
+        let load = |path: &str, body: &str| -> (PathBuf, Arc<dyn ConfigFile>) {
+            let path = env::HOME.join(path);
+            let cf = MiseToml::from_str(body, &path).unwrap();
+            (path, Arc::new(cf))
+        };
+        let mut config_files: ConfigMap = [
+            load(
+                "cwd/project/mise.toml",
+                "inherit_env = false\n[env]\nCHILD = '1'",
+            ),
+            load("cwd/mise.toml", "[env]\nPARENT = '1'"),
+        ]
+        .into_iter()
+        .collect();
+        let keys = |config_files: &ConfigMap| {
+            env_config_files(config_files)
+                .into_iter()
+                .flat_map(|(_, cf)| cf.env_entries().unwrap())
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&config_files), vec!["CHILD=1"]);
+        config_files.insert(
+            env::HOME.join("cwd/project/mise.toml"),
+            load("cwd/project/mise.toml", "[env]\nCHILD = '1'").1,
+        );
+        assert_eq!(keys(&config_files), vec!["CHILD=1", "PARENT=1"]);
+    }
+
+    #[test]
+    fn test_env_config_files_inherit_env_same_root() {
+        let load = |path: &str, body: &str| -> (PathBuf, Arc<dyn ConfigFile>) {
+            let path = env::HOME.join(path);
+            let cf = MiseToml::from_str(body, &path).unwrap();
+            (path, Arc::new(cf))
+        };
+        let config_files: ConfigMap = [
+            load(
+                "cwd/project/mise.local.toml",
+                "inherit_env = false\n[env]\nLOCAL = '1'",
+            ),
+            load("cwd/project/mise.toml", "[env]\nPROJECT = '1'"),
+            load(
+                "cwd/project/.config/mise/conf.d/extra.toml",
+                "[env]\nCONF_D = '1'",
+            ),
+            load("cwd/mise.toml", "[env]\nPARENT = '1'"),
+        ]
+        .into_iter()
+        .collect();
+        let keys = env_config_files(&config_files)
+            .into_iter()
+            .flat_map(|(_, cf)| cf.env_entries().unwrap())
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["LOCAL=1", "PROJECT=1", "CONF_D=1"]);
+    }
 
     #[tokio::test]
     async fn test_load() {
@@ -1481,4 +1561,87 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_final_env_env_finalize_hook() {
+        let loaded = Config::get().await;
+        let hook = Hook {
+            hook: Hooks::EnvFinalize,
+            script: indoc! {r#"
+                sed -n 's/^TEST_ENV_VAR=/FROM_STDIN=/p'
+                echo "ADDED_BY_HOOK=$MISE_PROJECT_ROOT"
+                echo "HOOK_ENV=${TEST_ENV_VAR:-unset},$MISE_OFFLINE"
+                echo "unset TEST_ENV_VAR"
+            "#}
+            .into(),
+            shell: None,
+        };
+        let root = env::HOME.join("cwd");
+        let config = config_with(
+            &loaded,
+            loaded.config_files.clone(),
+            OnceCell::new_with(Some(vec![(root.clone(), hook)])),
+        );
+        let (env, _) = Toolset::default().final_env(&config).await.unwrap();
+        assert_eq!(
+            env.get("ADDED_BY_HOOK"),
+            Some(&root.to_string_lossy().to_string())
+        );
+        // the env comes in on stdin, the hook itself runs offline in the env mise started with
+        assert_eq!(env.get("FROM_STDIN").unwrap(), "test-123");
+        assert_eq!(env.get("HOOK_ENV").unwrap(), "unset,1");
+        assert!(!env.contains_key("TEST_ENV_VAR"));
+    }
+
+    #[tokio::test]
+    async fn test_env_var_matches_final_env() {
+        let loaded = Config::get().await;
+        let with_env = |body: &str| {
+            let path = env::HOME.join("cwd/mise.toml");
+            let cf: Arc<dyn ConfigFile> = Arc::new(MiseToml::from_str(body, &path).unwrap());
+            let mut config_files = loaded.config_files.clone();
+            config_files.shift_insert(0, path, cf);
+            config_with(&loaded, config_files, OnceCell::new())
+        };
+        let ts = Toolset::default();
+        let home = env::HOME.join("tiny").to_string_lossy().to_string();
+        for body in [
+            "[env]\nTINY_HOME = '~/tiny'\nTEST_ENV_VAR = '!unset'",
+            "[env]\nTINY_HOME = '~/tiny'\nTEST_ENV_VAR = false",
+        ] {
+            let config = with_env(body);
+            let (env, _) = ts.final_env(&config).await.unwrap();
+            assert_eq!(env.get("TINY_HOME"), Some(&home));
+            assert_eq!(env.get("TEST_ENV_VAR"), None);
+            for key in ["TINY_HOME", "TEST_ENV_VAR"] {
+                let v = ts.env_var(&config, key).await.unwrap();
+                assert_eq!(v.as_ref(), env.get(key), "{key} with {body:?}");
+            }
+        }
+    }
+
+    /// `loaded` with other config files and hooks
+    fn config_with(
+        loaded: &Config,
+        config_files: ConfigMap,
+        hooks: OnceCell<Vec<(PathBuf, Hook)>>,
+    ) -> Config {
+        Config {
+            config_files,
+            project_root: loaded.project_root.clone(),
+            all_aliases: loaded.all_aliases.clone(),
+            repo_urls: loaded.repo_urls.clone(),
+            vars: loaded.vars.clone(),
+            tera_ctx: loaded.tera_ctx.clone(),
+            shorthands: loaded.shorthands.clone(),
+            aliases: loaded.aliases.clone(),
+            env: OnceCell::new(),
+            env_with_sources: OnceCell::new(),
+            hooks,
+            tasks: OnceCell::new(),
+            tool_request_set: OnceCell::new(),
+            toolset: OnceCell::new(),
+        }
+    }
 }
@@ -20,7 +20,7 @@ use std::sync::LazyLock as Lazy;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use std::{
-    collections::{BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     sync::atomic::Ordering,
 };
 use url::Url;
@@ -399,6 +399,41 @@ impl Settings {
         duration::parse_duration(&self.http_timeout).unwrap()
     }
 
+    pub fn install_lock_timeout(&self) -> Duration {
+        duration::parse_duration(&self.install_lock_timeout).unwrap()
+    }
+
+    pub fn install_retry_delay(&self) -> Duration {
+        duration::parse_duration(&self.install_retry_delay).unwrap()
+    }
+
+    pub fn install_timeout(&self) -> Option<Duration> {
+        let timeout = duration::parse_duration(&self.install_timeout).unwrap();
+        if timeout.is_zero() {
+            None
+        } else {
+            Some(timeout)
+        }
+    }
+
+    /// jobs_per_backend as backend type => max concurrent installs. malformed entries and limits
+    /// of 0 are ignored with a warning.
+    pub fn jobs_per_backend(&self) -> BTreeMap<String, usize> {
+        self.jobs_per_backend
+            .iter()
+            .filter_map(|entry| {
+                let limit = entry
+                    .split_once('=')
+                    .and_then(|(backend, jobs)| Some((backend.trim(), jobs.trim().parse().ok()?)))
+                    .filter(|(backend, jobs)| !backend.is_empty() && *jobs > 0);
+                if limit.is_none() {
+                    warn!("invalid jobs_per_backend entry, expected <backend>=<jobs>: {entry}");
+                }
+                limit.map(|(backend, jobs)| (backend.to_string(), jobs))
+            })
+            .collect()
+    }
+
     pub fn log_level(&self) -> log::LevelFilter {
         self.log_level.parse().unwrap_or(log::LevelFilter::Info)
     }
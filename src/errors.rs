@@ -3,7 +3,7 @@ use std::process::ExitStatus;
 
 use crate::cli::args::BackendArg;
 use crate::file::display_path;
-use crate::toolset::{ToolRequest, ToolSource};
+use crate::toolset::{ToolRequest, ToolSource, ToolVersion};
 use eyre::Report;
 use thiserror::Error;
 
@@ -15,6 +15,12 @@ pub enum Error {
         ts: ToolSource,
         source: Report,
     },
+    /// context for a failed install, `log` is empty unless `capture_logs` was set
+    #[error("failed to install {tv}")]
+    InstallFailed {
+        tv: Box<ToolVersion>,
+        log: Vec<String>,
+    },
     #[error("[{0}] plugin not installed")]
     PluginNotInstalled(String),
     #[error("{0}@{1} not installed")]
@@ -3,7 +3,7 @@ use std::collections::{BTreeSet, HashMap};
 use std::fmt::Display;
 use std::fs;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Seek, Write};
 #[cfg(unix)]
 use std::os::unix::fs::symlink;
 #[cfg(unix)]
@@ -21,7 +21,6 @@ use tar::Archive;
 use walkdir::WalkDir;
 use zip::ZipArchive;
 
-#[cfg(windows)]
 use crate::config::SETTINGS;
 use crate::ui::progress_report::SingleReport;
 use crate::{dirs, env};
@@ -57,6 +56,19 @@ pub fn append<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()
         .wrap_err_with(|| format!("failed append: {}", display_path(path)))
 }
 
+/// free bytes on the filesystem holding `path` or its closest existing parent
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    let path = path.ancestors().find(|p| p.exists())?;
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    Some(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+#[cfg(windows)]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
 pub fn remove_all<P: AsRef<Path>>(path: P) -> Result<()> {
     let path = path.as_ref();
     match path.metadata().map(|m| m.file_type()) {
@@ -569,6 +581,12 @@ pub fn which_non_pristine<P: AsRef<Path>>(name: P) -> Option<PathBuf> {
     _which(name, &env::PATH_NON_PRISTINE)
 }
 
+/// returns the first executable in `paths`, searching nothing else. for callers that have already
+/// computed a restricted PATH, e.g. a sandboxed `mise exec`
+pub fn which_in<P: AsRef<Path>>(name: P, paths: &[PathBuf]) -> Option<PathBuf> {
+    _which(name, paths)
+}
+
 fn _which<P: AsRef<Path>>(name: P, paths: &[PathBuf]) -> Option<PathBuf> {
     let name = name.as_ref();
     paths.iter().find_map(|path| {
@@ -726,7 +744,7 @@ fn open_tar(format: TarFormat, archive: &Path) -> Result<Box<dyn std::io::Read>>
     let f = File::open(archive)?;
     Ok(match format {
         TarFormat::TarGz => Box::new(GzDecoder::new(f)),
-        TarFormat::TarXz => Box::new(xz2::read::XzDecoder::new(f)),
+        TarFormat::TarXz => open_xz(f, SETTINGS.extract_jobs, MT_XZ_MIN_SIZE)?,
         TarFormat::TarBz2 => Box::new(bzip2::read::BzDecoder::new(f)),
         TarFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(f)?),
         TarFormat::Zip => bail!("zip format not supported"),
@@ -740,6 +758,25 @@ fn open_tar(format: TarFormat, archive: &Path) -> Result<Box<dyn std::io::Read>>
     })
 }
 
+/// xz archives smaller than this are always decompressed on the extracting thread
+const MT_XZ_MIN_SIZE: u64 = 32 * 1024 * 1024;
+
+/// opens an xz stream. large archives made of several blocks are decompressed on up to
+/// `extract_jobs` threads, anything else on the extracting thread
+fn open_xz(mut f: File, jobs: usize, min_size: u64) -> Result<Box<dyn Read>> {
+    if jobs > 1 && f.metadata()?.len() >= min_size {
+        match crate::xz::Index::read(&mut f) {
+            Ok(index) if index.len() > 1 => {
+                return Ok(Box::new(crate::xz::MtDecoder::spawn(f, index, jobs)?));
+            }
+            Ok(_) => debug!("xz archive is a single block, decompressing it on one thread"),
+            Err(err) => debug!("decompressing xz archive on one thread: {err:#}"),
+        }
+        f.rewind()?;
+    }
+    Ok(Box::new(xz2::read::XzDecoder::new(f)))
+}
+
 pub fn unzip(archive: &Path, dest: &Path) -> Result<()> {
     // TODO: show progress
     debug!("unzip {} -d {}", archive.display(), dest.display());
@@ -876,4 +913,68 @@ mod tests {
         assert_eq!(replace_path(Path::new("~/cwd")), dirs::HOME.join("cwd"));
         assert_eq!(replace_path(Path::new("/cwd")), Path::new("/cwd"));
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_which_in() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (a, b) = (tmp.path().join("a"), tmp.path().join("b"));
+        create_dir_all(&a).unwrap();
+        create_dir_all(&b).unwrap();
+        write(b.join("restricted"), "#!/bin/sh\n").unwrap();
+        make_executable(b.join("restricted")).unwrap();
+        assert_eq!(
+            which_in("restricted", &[a.clone(), b.clone()]),
+            Some(b.join("restricted"))
+        );
+        assert_eq!(which_in("restricted", &[a]), None);
+        // not found on PATH either, only `paths` is searched
+        assert_eq!(which_in("sh", &[b]), None);
+    }
+
+    #[test]
+    fn test_mt_xz_extract() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("large.tar.xz");
+        // small blocks so the archive can be decoded in parallel
+        let stream = xz2::stream::MtStreamBuilder::new()
+            .threads(4)
+            .block_size(256 * 1024)
+            .preset(1)
+            .encoder()
+            .unwrap();
+        let mut builder = tar::Builder::new(xz2::write::XzEncoder::new_stream(
+            File::create(&archive).unwrap(),
+            stream,
+        ));
+        for i in 0..4u32 {
+            // pseudo-random so the archive spans many blocks
+            let data = (0..1024 * 1024u32)
+                .map(|n| (n.wrapping_mul(2654435761).wrapping_add(i) >> 13) as u8)
+                .collect_vec();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, format!("dir/file{i}"), &data[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        let index = crate::xz::Index::read(&mut File::open(&archive).unwrap()).unwrap();
+        assert!(index.len() > 1, "{index:?}");
+
+        let extract = |jobs, dest: &str| {
+            let f = File::open(&archive).unwrap();
+            let reader = open_xz(f, jobs, 0).unwrap();
+            Archive::new(reader).unpack(tmp.path().join(dest)).unwrap();
+        };
+        extract(1, "single");
+        extract(4, "threaded");
+        for i in 0..4 {
+            let single = fs::read(tmp.path().join(format!("single/dir/file{i}"))).unwrap();
+            let threaded = fs::read(tmp.path().join(format!("threaded/dir/file{i}"))).unwrap();
+            assert_eq!(single.len(), 1024 * 1024);
+            assert!(single == threaded, "file{i} differs");
+        }
+    }
 }
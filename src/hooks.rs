@@ -1,9 +1,10 @@
 use crate::cmd::cmd;
 use crate::config::{Config, SETTINGS, config_file};
+use crate::env_diff::EnvMap;
 use crate::shell::Shell;
 use crate::toolset::Toolset;
-use crate::{dirs, hook_env};
-use eyre::{Result, eyre};
+use crate::{dirs, env, hook_env};
+use eyre::{Result, bail, eyre};
 use indexmap::IndexSet;
 use itertools::Itertools;
 use std::iter::once;
@@ -32,6 +33,9 @@ pub enum Hooks {
     Cd,
     Preinstall,
     Postinstall,
+    #[serde(rename = "env_finalize")]
+    #[strum(serialize = "env_finalize")]
+    EnvFinalize,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -117,6 +121,77 @@ pub async fn run_one_hook(ts: &Toolset, hook: Hooks, shell: Option<&dyn Shell>)
     }
 }
 
+/// set for `env_finalize` hooks so mise run from them neither recurses nor installs anything
+const ENV_FINALIZE_VAR: &str = "__MISE_ENV_FINALIZE";
+
+/// runs `env_finalize` hooks against the computed env. the env is given to each hook on stdin as
+/// `KEY=VALUE` lines and the only thing a hook can do to it is print changes: `KEY=VALUE` or
+/// `unset KEY`. hooks run offline and can't install or exec tools through mise, see
+/// [`deny_in_env_finalize`].
+pub async fn run_env_finalize(config: &Config, env: &mut EnvMap) -> Result<()> {
+    // the hook may call back into mise which would compute the env again
+    if env::var_os(ENV_FINALIZE_VAR).is_some() {
+        return Ok(());
+    }
+    for (root, h) in config.hooks().await? {
+        if h.hook != Hooks::EnvFinalize {
+            continue;
+        }
+        SETTINGS.ensure_experimental("hooks")?;
+        trace!("running hook {} in {root:?}", h.hook);
+        let shell = SETTINGS.default_inline_shell()?;
+        let args = shell
+            .iter()
+            .skip(1)
+            .map(|s| s.as_str())
+            .chain(once(h.script.as_str()))
+            .collect_vec();
+        let mut hook_env = env::PRISTINE_ENV.clone().into_iter().collect::<EnvMap>();
+        hook_env.insert(
+            "MISE_PROJECT_ROOT".to_string(),
+            root.to_string_lossy().to_string(),
+        );
+        hook_env.insert(ENV_FINALIZE_VAR.to_string(), "1".to_string());
+        hook_env.insert("MISE_OFFLINE".to_string(), "1".to_string());
+        let stdin = env.iter().map(|(k, v)| format!("{k}={v}\n")).join("");
+        let output = cmd(&shell[0], args)
+            .full_env(hook_env)
+            .stdin_bytes(stdin)
+            .read()?;
+        apply_env_changes(env, &output)
+            .map_err(|e| eyre!("env_finalize hook in {}: {e}", root.display()))?;
+    }
+    Ok(())
+}
+
+/// fails when run from an `env_finalize` hook, for commands that install or run tools
+pub fn deny_in_env_finalize(command: &str) -> Result<()> {
+    if env::var_os(ENV_FINALIZE_VAR).is_some() {
+        bail!("mise {command} can't be run from an env_finalize hook");
+    }
+    Ok(())
+}
+
+fn apply_env_changes(env: &mut EnvMap, output: &str) -> Result<()> {
+    for line in output.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        if let Some(key) = line.strip_prefix("unset ") {
+            env.remove(key.trim());
+            continue;
+        }
+        match line.split_once('=') {
+            Some((k, v)) if !k.is_empty() && !k.contains(char::is_whitespace) => {
+                env.insert(k.to_string(), v.to_string());
+            }
+            _ => {
+                return Err(eyre!(
+                    "expected `KEY=VALUE` or `unset KEY` but got `{line}`"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Hook {
     pub fn from_toml(hook: Hooks, value: toml::Value) -> Result<Vec<Self>> {
         match value {
@@ -191,3 +266,28 @@ async fn execute(ts: &Toolset, root: &Path, hook: &Hook) -> Result<()> {
         .run()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_env_changes() {
+        let mut env = EnvMap::from([
+            ("EMPTY".to_string(), "".to_string()),
+            ("KEEP".to_string(), "1".to_string()),
+        ]);
+        apply_env_changes(&mut env, "unset EMPTY\nADDED=a=b\n\nKEEP=2\n").unwrap();
+        assert_eq!(
+            env,
+            EnvMap::from([
+                ("ADDED".to_string(), "a=b".to_string()),
+                ("KEEP".to_string(), "2".to_string()),
+            ])
+        );
+        assert!(apply_env_changes(&mut env, "mise install node").is_err());
+        assert!(apply_env_changes(&mut env, "=oops").is_err());
+    }
+}
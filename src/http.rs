@@ -1,16 +1,18 @@
 use std::io::Write;
 use std::path::Path;
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use eyre::{Report, Result, bail, ensure};
 use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::{ClientBuilder, IntoUrl, Response};
+use reqwest::{ClientBuilder, IntoUrl, NoProxy, Proxy, Response};
 use std::sync::LazyLock as Lazy;
 use url::Url;
 
 use crate::cli::version;
 use crate::config::SETTINGS;
 use crate::file::display_path;
+use crate::integrity;
 use crate::ui::progress_report::SingleReport;
 use crate::{env, file};
 
@@ -23,18 +25,34 @@ pub static HTTP: Lazy<Client> = Lazy::new(|| Client::new(SETTINGS.http_timeout()
 pub static HTTP_FETCH: Lazy<Client> =
     Lazy::new(|| Client::new(SETTINGS.fetch_remote_versions_timeout()).unwrap());
 
+/// shared by every client so download_rate_limit caps the process as a whole
+static DOWNLOAD_RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::default);
+
 #[derive(Debug)]
 pub struct Client {
     reqwest: reqwest::Client,
+    /// bytes/sec for download_file, from download_rate_limit
+    rate_limit: Option<u64>,
 }
 
 impl Client {
     fn new(timeout: Duration) -> Result<Self> {
+        let proxy = match &SETTINGS.proxy {
+            Some(url) => Some(proxy(url, NoProxy::from_env())?),
+            None => None,
+        };
+        Self::with_proxy(timeout, proxy)
+    }
+
+    /// without a `proxy` reqwest uses the one from HTTPS_PROXY/HTTP_PROXY/ALL_PROXY
+    fn with_proxy(timeout: Duration, proxy: Option<Proxy>) -> Result<Self> {
+        let mut builder = Self::_new().read_timeout(timeout).connect_timeout(timeout);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
         Ok(Self {
-            reqwest: Self::_new()
-                .read_timeout(timeout)
-                .connect_timeout(timeout)
-                .build()?,
+            reqwest: builder.build()?,
+            rate_limit: SETTINGS.download_rate_limit.filter(|r| *r > 0),
         })
     }
 
@@ -200,6 +218,8 @@ impl Client {
             .map(|(json, _)| json)
     }
 
+    /// while a tool is installed with an integrity manifest, only its listed artifact can be
+    /// downloaded and it has to match the listed checksum
     pub async fn download_file<U: IntoUrl>(
         &self,
         url: U,
@@ -207,6 +227,7 @@ impl Client {
         pr: Option<&Box<dyn SingleReport>>,
     ) -> Result<()> {
         let url = url.into_url()?;
+        let artifact = integrity::artifact_for_download(url.as_str())?;
         debug!("GET Downloading {} to {}", &url, display_path(path));
 
         let mut resp = self.get_async(url).await?;
@@ -224,12 +245,40 @@ impl Client {
             if let Some(pr) = pr {
                 pr.inc(chunk.len() as u64);
             }
+            if let Some(rate) = self.rate_limit {
+                let wait = DOWNLOAD_RATE_LIMITER.reserve(chunk.len() as u64, rate);
+                tokio::time::sleep(wait).await;
+            }
+        }
+        if let Some(artifact) = artifact {
+            file.flush()?;
+            artifact.verify(file.path())?;
         }
         file.persist(path)?;
         Ok(())
     }
 }
 
+/// paces downloads to roughly `rate` bytes/sec. each chunk books the next slot of time on a
+/// shared schedule and its download waits until the slot is over, so concurrent downloads split the
+/// rate between them. idle time is not saved up for a later burst.
+#[derive(Debug, Default)]
+struct RateLimiter {
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// how long to wait after receiving `bytes`
+    fn reserve(&self, bytes: u64, rate: u64) -> Duration {
+        let now = Instant::now();
+        let mut next = self.next.lock().unwrap();
+        let start = next.map_or(now, |n| n.max(now));
+        let end = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+        *next = Some(end);
+        end - now
+    }
+}
+
 pub fn error_code(e: &Report) -> Option<u16> {
     if e.to_string().contains("404") {
         // TODO: not this when I can figure out how to use eyre properly
@@ -242,6 +291,27 @@ pub fn error_code(e: &Report) -> Option<u16> {
     }
 }
 
+/// whether an error looks like a temporary network failure that may succeed if tried again
+/// e.g.: timeouts, dropped connections, 429s, and 5xx responses
+pub fn is_transient_error(e: &Report) -> bool {
+    e.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            match err.status() {
+                Some(status) => status.is_server_error() || status.as_u16() == 429,
+                None => err.is_timeout() || err.is_connect() || err.is_request(),
+            }
+        } else if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind::*;
+            matches!(
+                err.kind(),
+                ConnectionReset | ConnectionAborted | ConnectionRefused | TimedOut | UnexpectedEof
+            )
+        } else {
+            false
+        }
+    })
+}
+
 fn github_headers(url: &Url) -> HeaderMap {
     let mut headers = HeaderMap::new();
     if url.host_str() == Some("api.github.com") {
@@ -283,3 +353,133 @@ fn display_github_rate_limit(resp: &Response) {
         }
     }
 }
+
+/// `url` as the proxy for every request except to hosts in `no_proxy`
+fn proxy(url: &str, no_proxy: Option<NoProxy>) -> Result<Proxy> {
+    Ok(Proxy::all(url)?.no_proxy(no_proxy))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cli::args::BackendArg;
+    use crate::hash::hash_sha256_to_str;
+    use crate::integrity::IntegrityManifest;
+    use crate::toolset::{ToolRequest, ToolSource, ToolVersion};
+
+    #[tokio::test]
+    async fn test_download_file_integrity() {
+        let mut server = mockito::Server::new_async().await;
+        for name in ["listed", "unlisted"] {
+            server
+                .mock("GET", format!("/{name}.tar.gz").as_str())
+                .with_body("tarball")
+                .create_async()
+                .await;
+        }
+        let manifest = IntegrityManifest::parse(&format!(
+            r#"
+            ["core:zig"."0.13.0"]
+            url = "{}/listed.tar.gz"
+            sha256 = "{}"
+            size = 7
+            "#,
+            server.url(),
+            hash_sha256_to_str("tarball")
+        ))
+        .unwrap();
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let tr = ToolRequest::new(ba, "0.13.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "0.13.0".into());
+        let artifact = manifest.ensure_listed(&tv).unwrap().clone();
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("zig.tar.gz");
+
+        let url = format!("{}/unlisted.tar.gz", server.url());
+        let err = integrity::scope(
+            &tv,
+            Some(artifact.clone()),
+            HTTP.download_file(&url, &path, None),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{url} is not in the integrity manifest for {tv}")
+        );
+        assert!(!path.exists());
+        // downloads that aren't made while installing a tool are not gated
+        HTTP.download_file(&url, &path, None).await.unwrap();
+        file::remove_file(&path).unwrap();
+
+        let url = format!("{}/listed.tar.gz", server.url());
+        integrity::scope(&tv, Some(artifact), HTTP.download_file(&url, &path, None))
+            .await
+            .unwrap();
+        assert_eq!(file::read_to_string(&path).unwrap(), "tarball");
+    }
+
+    #[tokio::test]
+    async fn test_download_rate_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let body = vec![b'z'; 4000];
+        let _mock = server
+            .mock("GET", "/zig.tar.xz")
+            .with_body(&body)
+            .create_async()
+            .await;
+        let mut client = Client::with_proxy(Duration::from_secs(5), None).unwrap();
+        client.rate_limit = Some(8000);
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("zig.tar.xz");
+        let start = Instant::now();
+        client
+            .download_file(format!("{}/zig.tar.xz", server.url()), &path, None)
+            .await
+            .unwrap();
+        // 4000 bytes at 8000 bytes/sec
+        assert!(
+            start.elapsed() >= Duration::from_millis(500),
+            "{:?}",
+            start.elapsed()
+        );
+        assert_eq!(file::read(&path).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let mut proxy_server = mockito::Server::new_async().await;
+        let proxied = proxy_server
+            .mock("GET", "/index.json")
+            .with_body("from proxy")
+            .expect(1)
+            .create_async()
+            .await;
+        let mut direct_server = mockito::Server::new_async().await;
+        let direct = direct_server
+            .mock("GET", "/index.json")
+            .with_body("direct")
+            .expect(1)
+            .create_async()
+            .await;
+        let proxy = proxy(&proxy_server.url(), NoProxy::from_string("127.0.0.1")).unwrap();
+        let client = Client::with_proxy(Duration::from_secs(5), Some(proxy)).unwrap();
+
+        // the host doesn't resolve so this only works if the proxy is used
+        let text = client
+            .get_text("http://ziglang.invalid/index.json")
+            .await
+            .unwrap();
+        assert_eq!(text, "from proxy");
+        // NO_PROXY hosts are requested directly
+        let text = client
+            .get_text(format!("{}/index.json", direct_server.url()))
+            .await
+            .unwrap();
+        assert_eq!(text, "direct");
+        proxied.assert_async().await;
+        direct.assert_async().await;
+    }
+}
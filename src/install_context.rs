@@ -1,5 +1,6 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use crate::sbom::SbomEntry;
 use crate::toolset::Toolset;
 use crate::ui::progress_report::SingleReport;
 
@@ -7,4 +8,13 @@ pub struct InstallContext {
     pub ts: Arc<Toolset>,
     pub pr: Box<dyn SingleReport>,
     pub force: bool,
+    /// provenance the backend recorded while installing, see [crate::sbom]
+    pub sbom: Arc<Mutex<Option<SbomEntry>>>,
+}
+
+impl InstallContext {
+    /// records where the install came from for the SBOM and install receipt
+    pub fn record_sbom(&self, entry: SbomEntry) {
+        *self.sbom.lock().unwrap() = Some(entry);
+    }
 }
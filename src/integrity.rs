@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use eyre::{Result, WrapErr, bail};
+use serde_derive::Deserialize;
+
+use crate::config::Settings;
+use crate::file::display_path;
+use crate::toolset::ToolVersion;
+use crate::{file, hash, minisign};
+
+/// the one artifact a tool version may be installed from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Artifact {
+    pub url: String,
+    pub sha256: String,
+    pub size: Option<u64>,
+}
+
+/// vetted artifacts from the `integrity_manifest` setting, keyed by full tool name then version
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct IntegrityManifest {
+    tools: BTreeMap<String, BTreeMap<String, Artifact>>,
+}
+
+/// the manifest from settings, if one is configured
+pub fn manifest() -> Result<Option<&'static IntegrityManifest>> {
+    static MANIFEST: OnceLock<Option<IntegrityManifest>> = OnceLock::new();
+    if let Some(manifest) = MANIFEST.get() {
+        return Ok(manifest.as_ref());
+    }
+    let settings = Settings::get();
+    let manifest = match &settings.integrity_manifest {
+        Some(path) => Some(IntegrityManifest::load(
+            path,
+            settings.integrity_manifest_key.as_deref(),
+        )?),
+        None => None,
+    };
+    Ok(MANIFEST.get_or_init(|| manifest).as_ref())
+}
+
+impl IntegrityManifest {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// reads the manifest at `path`, checking it against `<path>.minisig` when `key` is set
+    pub fn load(path: &Path, key: Option<&str>) -> Result<Self> {
+        let body = file::read_to_string(path)?;
+        if let Some(key) = key {
+            let sig = file::read_to_string(format!("{}.minisig", path.display()))?;
+            minisign::verify(key, body.as_bytes(), &sig).wrap_err_with(|| {
+                format!(
+                    "invalid signature for integrity manifest {}",
+                    display_path(path)
+                )
+            })?;
+        }
+        Self::parse(&body)
+            .wrap_err_with(|| format!("failed to parse integrity manifest {}", display_path(path)))
+    }
+
+    /// errors unless `tv` is listed, called before anything is downloaded for it
+    pub fn ensure_listed(&self, tv: &ToolVersion) -> Result<&Artifact> {
+        match self
+            .tools
+            .get(&tv.ba().full())
+            .and_then(|v| v.get(&tv.version))
+        {
+            Some(artifact) => Ok(artifact),
+            None => bail!("{tv} is not in the integrity manifest"),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// the tool version the current task is installing and its listed artifact, see `scope`
+    static INSTALLING: Option<(String, Artifact)>;
+}
+
+/// runs the install of `tv` with every download it makes gated on `artifact`, the one listed for
+/// `tv`. downloads outside of a tool install, like plugins or remote tasks, are not gated.
+pub async fn scope<F: Future>(tv: &ToolVersion, artifact: Option<Artifact>, fut: F) -> F::Output {
+    INSTALLING
+        .scope(artifact.map(|a| (tv.to_string(), a)), fut)
+        .await
+}
+
+/// the listed artifact `url` has to match when downloaded by the current task, errors if the tool
+/// version being installed was listed with a different url
+pub fn artifact_for_download(url: &str) -> Result<Option<Artifact>> {
+    INSTALLING
+        .try_with(|installing| match installing {
+            Some((_, artifact)) if artifact.url == url => Ok(Some(artifact.clone())),
+            Some((tv, _)) => bail!("{url} is not in the integrity manifest for {tv}"),
+            None => Ok(None),
+        })
+        .unwrap_or(Ok(None))
+}
+
+impl Artifact {
+    /// checks a downloaded file against the size and sha256 in the manifest
+    pub fn verify(&self, path: &Path) -> Result<()> {
+        if let Some(size) = self.size {
+            let actual = file::size(path)?;
+            if actual != size {
+                bail!(
+                    "size mismatch for {}: the integrity manifest expects {size} bytes but got {actual}",
+                    self.url
+                );
+            }
+        }
+        hash::ensure_checksum(path, &self.sha256, None, "sha256")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cli::args::BackendArg;
+    use crate::toolset::{ToolRequest, ToolSource};
+
+    #[test]
+    fn test_ensure_listed() {
+        let manifest = IntegrityManifest::parse(
+            r#"
+            ["asdf:tiny"."1.0.0"]
+            url = "https://example.com/tiny-1.0.0.tar.gz"
+            sha256 = "abc"
+            "#,
+        )
+        .unwrap();
+        let tv = |v: &str| {
+            let ba: Arc<BackendArg> = Arc::new("tiny".into());
+            ToolVersion::new(
+                ToolRequest::new(ba, v, ToolSource::Argument).unwrap(),
+                v.into(),
+            )
+        };
+        let artifact = manifest.ensure_listed(&tv("1.0.0")).unwrap();
+        assert_eq!(artifact.url, "https://example.com/tiny-1.0.0.tar.gz");
+        assert_eq!(
+            manifest
+                .ensure_listed(&tv("2.0.0"))
+                .unwrap_err()
+                .to_string(),
+            "asdf:tiny@2.0.0 is not in the integrity manifest"
+        );
+    }
+}
@@ -1,16 +1,18 @@
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use eyre::Result;
+use eyre::{Report, Result, eyre};
 
 use crate::dirs;
 use crate::file::{create_dir_all, display_path};
 use crate::hash::hash_to_str;
 
-pub type OnLockedFn = Box<dyn Fn(&Path)>;
+pub type OnLockedFn = Box<dyn Fn(&Path) + Send + Sync>;
 
 pub struct LockFile {
     path: PathBuf,
     on_locked: Option<OnLockedFn>,
+    timeout: Option<Duration>,
 }
 
 impl LockFile {
@@ -19,32 +21,85 @@ impl LockFile {
         Self {
             path,
             on_locked: None,
+            timeout: None,
         }
     }
 
     pub fn with_callback<F>(mut self, cb: F) -> Self
     where
-        F: Fn(&Path) + 'static,
+        F: Fn(&Path) + Send + Sync + 'static,
     {
         self.on_locked = Some(Box::new(cb));
         self
     }
 
-    pub fn lock(self) -> Result<fslock::LockFile> {
+    /// give up waiting for the lock after this long instead of blocking forever
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn open(&self) -> Result<fslock::LockFile> {
         if let Some(parent) = self.path.parent() {
             create_dir_all(parent)?;
         }
-        let mut lock = fslock::LockFile::open(&self.path)?;
+        Ok(fslock::LockFile::open(&self.path)?)
+    }
+
+    fn timed_out(&self) -> Report {
+        eyre!(
+            "timed out after {}s waiting for lock on {}",
+            self.timeout.unwrap_or_default().as_secs(),
+            display_path(&self.path)
+        )
+    }
+
+    pub fn lock(self) -> Result<fslock::LockFile> {
+        let mut lock = self.open()?;
         if !lock.try_lock()? {
-            if let Some(f) = self.on_locked {
+            if let Some(f) = &self.on_locked {
                 f(&self.path)
             }
-            lock.lock()?;
+            match self.timeout {
+                Some(timeout) => {
+                    let start = Instant::now();
+                    while !lock.try_lock()? {
+                        if start.elapsed() >= timeout {
+                            return Err(self.timed_out());
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                }
+                None => lock.lock()?,
+            }
+        }
+        Ok(lock)
+    }
+
+    /// lock for async callers, waiting with a tokio sleep between attempts so another process
+    /// holding the lock doesn't block a runtime worker
+    pub async fn lock_async(self) -> Result<fslock::LockFile> {
+        let mut lock = self.open()?;
+        if lock.try_lock()? {
+            return Ok(lock);
+        }
+        if let Some(f) = &self.on_locked {
+            f(&self.path)
+        }
+        let start = Instant::now();
+        while !lock.try_lock()? {
+            if self.timeout.is_some_and(|t| start.elapsed() >= t) {
+                return Err(self.timed_out());
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
         }
         Ok(lock)
     }
 }
 
+/// how often a lock held by another process is tried again
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 pub(crate) fn get(path: &Path, force: bool) -> eyre::Result<Option<fslock::LockFile>> {
     let lock = if force {
         None
@@ -58,3 +113,54 @@ pub(crate) fn get(path: &Path, force: bool) -> eyre::Result<Option<fslock::LockF
     };
     Ok(lock)
 }
+
+/// get but waiting asynchronously and giving up after `timeout`
+pub(crate) async fn get_async(
+    path: &Path,
+    force: bool,
+    timeout: Duration,
+) -> eyre::Result<Option<fslock::LockFile>> {
+    if force {
+        return Ok(None);
+    }
+    let lock = LockFile::new(path)
+        .with_timeout(timeout)
+        .with_callback(|l| {
+            debug!("waiting for lock on {}", display_path(l));
+        })
+        .lock_async()
+        .await?;
+    Ok(Some(lock))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_lock_serializes() {
+        let path = dirs::CACHE.join("test_lock_serializes");
+        let lock = LockFile::new(&path).lock().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let p = path.clone();
+        let waiter = thread::spawn(move || {
+            let res = LockFile::new(&p)
+                .with_timeout(Duration::from_millis(200))
+                .lock();
+            tx.send(res.is_ok()).unwrap();
+            let lock = LockFile::new(&p)
+                .with_timeout(Duration::from_secs(10))
+                .lock();
+            tx.send(lock.is_ok()).unwrap();
+        });
+        // the first attempt times out because the lock is still held
+        assert!(!rx.recv().unwrap());
+        drop(lock);
+        // the second attempt succeeds once the lock is released
+        assert!(rx.recv().unwrap());
+        waiter.join().unwrap();
+    }
+}
@@ -49,6 +49,7 @@ mod hook_env;
 mod hooks;
 mod http;
 mod install_context;
+mod integrity;
 mod lock_file;
 mod lockfile;
 pub(crate) mod logger;
@@ -63,6 +64,7 @@ mod redactions;
 mod registry;
 pub(crate) mod result;
 mod runtime_symlinks;
+mod sbom;
 mod shell;
 mod shims;
 mod shorthands;
@@ -78,6 +80,7 @@ mod uv;
 mod versions_host;
 mod watch_files;
 mod wildcard;
+mod xz;
 
 pub(crate) use crate::exit::exit;
 pub(crate) use crate::result::Result;
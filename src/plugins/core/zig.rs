@@ -1,36 +1,58 @@
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, LazyLock as Lazy},
 };
 
 use crate::backend::Backend;
 use crate::cli::args::BackendArg;
 use crate::cli::version::OS;
-use crate::cmd::CmdLineRunner;
-use crate::config::SETTINGS;
+use crate::config::{Config, SETTINGS, Settings};
+use crate::env_diff::EnvMap;
 use crate::file::TarOptions;
-use crate::http::{HTTP, HTTP_FETCH};
+use crate::file::display_path;
+use crate::http::{self, HTTP, HTTP_FETCH};
 use crate::install_context::InstallContext;
-use crate::toolset::ToolVersion;
+use crate::sbom::{self, SbomEntry};
+use crate::toolset::outdated_info::OutdatedInfo;
+use crate::toolset::{ToolVersion, Toolset};
 use crate::ui::progress_report::SingleReport;
-use crate::{file, github, minisign, plugins};
+use crate::{dirs, env, file, github, hash, minisign, plugins};
 use async_trait::async_trait;
-use eyre::Result;
+use dashmap::DashMap;
+use eyre::{Result, bail, eyre};
 use itertools::Itertools;
+use tokio::sync::OnceCell;
 use versions::Versioning;
 use xx::regex;
 
 #[derive(Debug)]
 pub struct ZigPlugin {
     ba: Arc<BackendArg>,
+    /// release lists and version indexes shared by concurrent installs, keyed by url
+    releases: DashMap<String, Arc<OnceCell<Vec<String>>>>,
+    indexes: DashMap<String, Arc<OnceCell<serde_json::Value>>>,
 }
 
+struct Download {
+    tarball_path: PathBuf,
+    url: String,
+    /// the dev version a named build like `ref:master` pointed to
+    resolved_version: Option<String>,
+    signature: Option<String>,
+}
+
+/// the version each named build like `master` resolved to, frozen for the process so every
+/// `ref:master` install in one run gets the same build even if ziglang.org updates mid-run
+static NAMED_BUILDS: Lazy<DashMap<String, Arc<OnceCell<String>>>> = Lazy::new(DashMap::new);
+
 const ZIG_MINISIGN_KEY: &str = "RWSGOq2NVecA2UPNdBUZykf1CCb147pkmdtYxgb3Ti+JO/wCYvhbAb/U";
 
 impl ZigPlugin {
     pub fn new() -> Self {
         Self {
             ba: Arc::new(plugins::core::new_backend_arg("zig")),
+            releases: Default::default(),
+            indexes: Default::default(),
         }
     }
 
@@ -44,84 +66,179 @@ impl ZigPlugin {
 
     fn test_zig(&self, ctx: &InstallContext, tv: &ToolVersion) -> Result<()> {
         ctx.pr.set_message("zig version".into());
-        CmdLineRunner::new(self.zig_bin(tv))
-            .with_pr(&ctx.pr)
-            .arg("version")
-            .execute()
+        let reported = cmd!(self.zig_bin(tv), "version").read()?;
+        ctx.pr.set_message(format!("zig version {reported}"));
+        if let Some(repo) = self.repo(tv) {
+            // fork release tags like `0.14.0-fork.2` are not what the fork's zig reports
+            debug!("skipping zig version check for {repo}");
+            return Ok(());
+        }
+        check_reported_version(tv, &reported)
     }
 
-    async fn download(&self, tv: &ToolVersion, pr: &Box<dyn SingleReport>) -> Result<PathBuf> {
-        let archive_ext = if cfg!(target_os = "windows") {
-            "zip"
-        } else {
-            "tar.xz"
-        };
-
-        let url = if tv.version == "ref:master" {
-            format!(
-                "https://ziglang.org/builds/zig-{}-{}-{}.{archive_ext}",
-                os(),
-                arch(),
-                self.get_version_from_json("master").await?
-            )
+    /// the mirrors to download `tv` from, plus the dev version for named builds like `ref:master`
+    async fn tarball_urls(&self, tv: &ToolVersion) -> Result<(Vec<String>, Option<String>)> {
+        let archive_ext = archive_ext();
+        let settings = Settings::get();
+        let mut resolved_version = None;
+        let urls = if tv.version == "ref:master" {
+            let version = self.get_version_from_json("master").await?;
+            let urls = ziglang_urls(
+                &settings,
+                &format!("builds/zig-{}-{}-{version}.{archive_ext}", os(), arch()),
+            );
+            resolved_version = Some(version);
+            urls
         } else if tv.version == "ref:mach-latest" {
-            format!(
-                "https://pkg.machengine.org/zig/zig-{}-{}-{}.{archive_ext}",
+            let version = self.get_version_from_json("mach-latest").await?;
+            let urls = vec![format!(
+                "https://pkg.machengine.org/zig/zig-{}-{}-{version}.{archive_ext}",
                 os(),
                 arch(),
-                self.get_version_from_json("mach-latest").await?
-            )
+            )];
+            resolved_version = Some(version);
+            urls
         } else if regex!(r"^[0-9]+\.[0-9]+\.[0-9]+-dev.[0-9]+\+[0-9a-f]+$").is_match(&tv.version) {
-            format!(
+            vec![format!(
                 "https://pkg.machengine.org/zig/zig-{}-{}-{}.{archive_ext}",
                 os(),
                 arch(),
                 tv.version
-            )
+            )]
         } else {
-            format!(
-                "https://ziglang.org/download/{}/zig-{}-{}-{}.{archive_ext}",
-                tv.version,
-                os(),
-                arch(),
-                tv.version
+            ziglang_urls(
+                &settings,
+                &format!(
+                    "download/{}/zig-{}-{}-{}.{archive_ext}",
+                    tv.version,
+                    os(),
+                    arch(),
+                    tv.version
+                ),
             )
         };
+        Ok((urls, resolved_version))
+    }
 
-        let filename = url.split('/').next_back().unwrap();
-        let tarball_path = tv.download_path().join(filename);
+    async fn download(&self, tv: &ToolVersion, pr: &Box<dyn SingleReport>) -> Result<Download> {
+        if let Some(repo) = self.repo(tv) {
+            return self.download_fork(tv, pr, &repo, archive_ext()).await;
+        }
 
-        pr.set_message(format!("download {filename}"));
-        HTTP.download_file(&url, &tarball_path, Some(pr)).await?;
+        let settings = Settings::get();
+        let (urls, resolved_version) = self.tarball_urls(tv).await?;
+        let filename = urls[0].split('/').next_back().unwrap().to_string();
+        let tarball_path = tv.download_path().join(&filename);
+        let (url, signature) = download_verified(
+            &urls,
+            &tarball_path,
+            verify_mode(tv)?,
+            settings.zig.tofu,
+            pr,
+        )
+        .await?;
 
-        pr.set_message(format!("minisign {filename}"));
-        let tarball_data = file::read(&tarball_path)?;
-        let sig = HTTP.get_text(format!("{url}.minisig")).await?;
-        minisign::verify(ZIG_MINISIGN_KEY, &tarball_data, &sig)?;
+        Ok(Download {
+            tarball_path,
+            url,
+            resolved_version,
+            signature: signature.map(str::to_string),
+        })
+    }
 
-        Ok(tarball_path)
+    /// the `repo` option of a zig fork, e.g. `zig[repo=myorg/zig]`
+    fn repo(&self, tv: &ToolVersion) -> Option<String> {
+        tv.request
+            .options()
+            .get("repo")
+            .or(self.ba.opts().get("repo"))
+            .cloned()
+    }
+
+    /// downloads a fork build from the release assets of its github repo
+    async fn download_fork(
+        &self,
+        tv: &ToolVersion,
+        pr: &Box<dyn SingleReport>,
+        repo: &str,
+        archive_ext: &str,
+    ) -> Result<Download> {
+        let release = github::get_release(repo, &tv.version).await?;
+        let asset = fork_asset(&release.assets, os(), arch(), archive_ext).ok_or_else(|| {
+            eyre!(
+                "no zig-{}-{} asset in release {} of {repo}",
+                os(),
+                arch(),
+                tv.version
+            )
+        })?;
+        let tarball_path = tv.download_path().join(&asset.name);
+        pr.set_message(format!("download {}", asset.name));
+        HTTP.download_file(&asset.browser_download_url, &tarball_path, Some(pr))
+            .await?;
+
+        let opts = tv.request.options();
+        let signature = if opts.get("minisign").is_some_and(|v| v == "false")
+            || verify_mode(tv)? == Verify::Checksum
+        {
+            debug!("skipping minisign verification for {repo}");
+            None
+        } else {
+            let sig_name = format!("{}.minisig", asset.name);
+            let sig_asset = release
+                .assets
+                .iter()
+                .find(|a| a.name == sig_name)
+                .ok_or_else(|| {
+                    eyre!("{sig_name} not found in {repo}, set minisign=false to skip verification")
+                })?;
+            pr.set_message(format!("minisign {}", asset.name));
+            let sig = HTTP.get_text(&sig_asset.browser_download_url).await?;
+            let key = opts
+                .get("minisign_key")
+                .map(|k| k.as_str())
+                .unwrap_or(ZIG_MINISIGN_KEY);
+            minisign::verify(key, &file::read(&tarball_path)?, &sig)?;
+            Some("minisign:verified".to_string())
+        };
+
+        Ok(Download {
+            tarball_path,
+            url: asset.browser_download_url.clone(),
+            resolved_version: None,
+            signature,
+        })
     }
 
     fn install(&self, ctx: &InstallContext, tv: &ToolVersion, tarball_path: &Path) -> Result<()> {
         let filename = tarball_path.file_name().unwrap().to_string_lossy();
         ctx.pr.set_message(format!("extract {filename}"));
-        file::remove_all(tv.install_path())?;
-        file::untar(
-            tarball_path,
-            &tv.install_path(),
-            &TarOptions {
-                strip_components: 1,
-                pr: Some(&ctx.pr),
-                ..Default::default()
-            },
-        )?;
+        install_atomic(&Settings::get(), &tv.install_path(), |dir| {
+            file::untar(
+                tarball_path,
+                dir,
+                &TarOptions {
+                    strip_components: 1,
+                    pr: Some(&ctx.pr),
+                    ..Default::default()
+                },
+            )?;
+            if tv
+                .request
+                .options()
+                .get("minimal")
+                .is_some_and(|v| v == "true")
+            {
+                ctx.pr.set_message("remove docs".into());
+                strip_minimal(dir)?;
+            }
 
-        if cfg!(unix) {
-            file::create_dir_all(tv.install_path().join("bin"))?;
-            file::make_symlink(Path::new("../zig"), &tv.install_path().join("bin/zig"))?;
-        }
-
-        Ok(())
+            if cfg!(unix) {
+                file::create_dir_all(dir.join("bin"))?;
+                file::make_symlink(Path::new("../zig"), &dir.join("bin/zig"))?;
+            }
+            Ok(())
+        })
     }
 
     fn verify(&self, ctx: &InstallContext, tv: &ToolVersion) -> Result<()> {
@@ -129,21 +246,100 @@ impl ZigPlugin {
     }
 
     async fn get_version_from_json(&self, key: &str) -> Result<String> {
-        let json_url: &str = if key == "master" {
-            "https://ziglang.org/download/index.json"
-        } else if key == "mach-latest" {
-            "https://machengine.org/zig/index.json"
-        } else {
-            // Left blank for future sources just in case
-            ""
+        self.named_build(&NAMED_BUILDS, key).await
+    }
+
+    /// the version the named build `key` resolves to, frozen in `builds`
+    async fn named_build(
+        &self,
+        builds: &DashMap<String, Arc<OnceCell<String>>>,
+        key: &str,
+    ) -> Result<String> {
+        let json_url = index_url(&Settings::get(), key)?;
+        self.named_build_version(builds, &json_url, key).await
+    }
+
+    /// outdated_info for a named build, checked against the versions frozen in `builds`
+    async fn named_build_outdated(
+        &self,
+        builds: &DashMap<String, Arc<OnceCell<String>>>,
+        tv: &ToolVersion,
+    ) -> Result<Option<OutdatedInfo>> {
+        let Some(key) = tv
+            .version
+            .strip_prefix("ref:")
+            .filter(|k| ["master", "mach-latest"].contains(k))
+        else {
+            return Ok(None);
+        };
+        let Some(installed) =
+            sbom::read_receipt(&tv.install_path())?.and_then(|r| r.entry.resolved_version)
+        else {
+            return Ok(None);
+        };
+        let latest = self.named_build(builds, key).await?;
+        if version_sort_key(&latest) <= version_sort_key(&installed) {
+            return Ok(None);
+        }
+        let config = Config::get().await;
+        let mut oi = OutdatedInfo::new(&config, tv.clone(), latest)?;
+        oi.current = Some(installed);
+        Ok(Some(oi))
+    }
+
+    async fn named_build_version(
+        &self,
+        builds: &DashMap<String, Arc<OnceCell<String>>>,
+        json_url: &str,
+        key: &str,
+    ) -> Result<String> {
+        fetch_once(builds, key.to_string(), || async {
+            let version_json = fetch_once(&self.indexes, json_url.to_string(), || {
+                HTTP_FETCH.json::<serde_json::Value, _>(json_url)
+            })
+            .await?;
+            version_from_index(&version_json, key)
+                .ok_or_else(|| eyre::eyre!("Failed to get zig version from {:?}", json_url))
+        })
+        .await
+    }
+
+    /// the sha256 the version index publishes for the tarball of `tv` on this platform. named builds
+    /// only use it when the index still points to `resolved_version`, the build that was downloaded.
+    /// mach builds and dev versions have none.
+    async fn index_shasum(
+        &self,
+        tv: &ToolVersion,
+        resolved_version: Option<&str>,
+    ) -> Result<Option<String>> {
+        let (index, key) = match tv.version.strip_prefix("ref:") {
+            Some(key @ ("master" | "mach-latest")) => (key, key),
+            _ if tv.version.ends_with("-mach") => ("mach-latest", tv.version.as_str()),
+            _ => ("master", tv.version.as_str()),
         };
+        let json_url = index_url(&Settings::get(), index)?;
+        let index = fetch_once(&self.indexes, json_url.clone(), || {
+            HTTP_FETCH.json::<serde_json::Value, _>(&json_url)
+        })
+        .await?;
+        if resolved_version.is_some()
+            && version_from_index(&index, key).as_deref() != resolved_version
+        {
+            debug!("zig index for {key} moved on from {resolved_version:?}, skipping shasum");
+            return Ok(None);
+        }
+        Ok(shasum_from_index(
+            &index,
+            key,
+            &format!("{}-{}", arch(), os()),
+        ))
+    }
 
-        let version_json: serde_json::Value = HTTP_FETCH.json(json_url).await?;
-        let zig_version = version_json
-            .pointer(&format!("/{key}/version"))
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| eyre::eyre!("Failed to get zig version from {:?}", json_url))?;
-        Ok(zig_version.to_string())
+    async fn release_versions(&self, api_url: &str, repo: &str) -> Result<Vec<String>> {
+        fetch_once(&self.releases, format!("{api_url}/repos/{repo}"), || {
+            list_release_versions(api_url, repo)
+        })
+        .await
     }
 }
 
@@ -154,14 +350,9 @@ impl Backend for ZigPlugin {
     }
 
     async fn _list_remote_versions(&self) -> Result<Vec<String>> {
-        let versions: Vec<String> = github::list_releases("ziglang/zig")
-            .await?
-            .into_iter()
-            .map(|r| r.tag_name)
-            .unique()
-            .sorted_by_cached_key(|s| (Versioning::new(s), s.to_string()))
-            .collect();
-        Ok(versions)
+        let repo = self.ba.opts().get("repo").cloned();
+        let repo = repo.as_deref().unwrap_or("ziglang/zig");
+        self.release_versions(github::API_URL, repo).await
     }
 
     async fn list_bin_paths(&self, tv: &ToolVersion) -> Result<Vec<PathBuf>> {
@@ -181,12 +372,409 @@ impl Backend for ZigPlugin {
         ctx: &InstallContext,
         mut tv: ToolVersion,
     ) -> Result<ToolVersion> {
-        let tarball_path = self.download(&tv, &ctx.pr).await?;
+        let Download {
+            tarball_path,
+            url,
+            resolved_version,
+            signature,
+        } = self.download(&tv, &ctx.pr).await?;
+        let filename = tarball_path.file_name().unwrap().to_string_lossy();
+        if verify_mode(&tv)? == Verify::Checksum {
+            require_checksum(&tv, &filename)?;
+        }
+        if self.repo(&tv).is_none() {
+            // the index is only an extra check, an install shouldn't fail because it is unreachable
+            match self.index_shasum(&tv, resolved_version.as_deref()).await {
+                Ok(Some(shasum)) => check_index_shasum(ctx, &mut tv, &tarball_path, &shasum)?,
+                Ok(None) => {}
+                Err(err) => debug!("failed to get the zig index shasum for {tv}: {err:#}"),
+            }
+        }
         self.verify_checksum(ctx, &mut tv, &tarball_path)?;
         self.install(ctx, &tv, &tarball_path)?;
         self.verify(ctx, &tv)?;
+        ctx.record_sbom(SbomEntry {
+            resolved_version,
+            url: Some(url),
+            signature,
+            ..Default::default()
+        });
         Ok(tv)
     }
+
+    async fn exec_env(&self, _config: &Config, _ts: &Toolset, tv: &ToolVersion) -> Result<EnvMap> {
+        Ok(cache_env(&Settings::get(), tv))
+    }
+
+    async fn estimated_install_size(&self, tv: &ToolVersion) -> Result<Option<u64>> {
+        if self.repo(tv).is_some() || *env::OFFLINE {
+            return Ok(None);
+        }
+        let (urls, _) = self.tarball_urls(tv).await?;
+        let filename = urls[0].split('/').next_back().unwrap();
+        let size = tarball_size(&tv.download_path().join(filename), &urls[0]).await;
+        // the tarball plus what it extracts to, xz archives of zig are about a quarter of that
+        Ok(size.map(|size| size * 5))
+    }
+
+    fn is_retriable_error(&self, err: &eyre::Report) -> bool {
+        // a missing tarball will not show up by trying again
+        http::error_code(err) != Some(404) && http::is_transient_error(err)
+    }
+
+    /// named builds like `ref:master` are outdated when the index points to a newer dev build
+    /// than the one that was installed
+    async fn outdated_info(&self, tv: &ToolVersion, _bump: bool) -> Result<Option<OutdatedInfo>> {
+        self.named_build_outdated(&NAMED_BUILDS, tv).await
+    }
+}
+
+/// removed by `minimal=true`. only the html docs, `lib/` is left alone since the compiler needs
+/// the std lib sources and `zig std` serves `lib/docs`.
+const MINIMAL_STRIP: &[&str] = &["doc"];
+
+/// runs `populate` on an empty temp dir and only moves it to `install_path` once that succeeded, so
+/// a failed extraction never leaves a partial install behind. anything already at `install_path` is
+/// moved aside first and put back if the final rename fails, e.g. when zig.extract_dir is on
+/// another filesystem. the temp dir is a sibling of `install_path` unless zig.extract_dir is set.
+fn install_atomic(
+    settings: &Settings,
+    install_path: &Path,
+    populate: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    let Some(parent) = install_path.parent() else {
+        bail!("no parent dir for {}", display_path(install_path));
+    };
+    let dir = settings.zig.extract_dir.as_deref().unwrap_or(parent);
+    file::create_dir_all(dir)?;
+    let tmp = tempfile::Builder::new()
+        .prefix(".mise-extract-")
+        .tempdir_in(dir)?;
+    populate(tmp.path())?;
+    // removed along with its contents on drop, once the new install is in place
+    let old = tempfile::Builder::new()
+        .prefix(".mise-old-")
+        .tempdir_in(parent)?;
+    let backup = old.path().join("install");
+    let had_old = install_path.exists();
+    if had_old {
+        file::rename(install_path, &backup)?;
+    }
+    if let Err(err) = file::rename(tmp.path(), install_path) {
+        if had_old {
+            file::rename(&backup, install_path)?;
+        }
+        return Err(err);
+    }
+    // already moved, nothing left for the temp dir to clean up
+    let _ = tmp.keep();
+    Ok(())
+}
+
+/// the size of the tarball at `url`, taken from `tarball_path` when it was already downloaded. a
+/// failed HEAD request just means the size is unknown
+async fn tarball_size(tarball_path: &Path, url: &str) -> Option<u64> {
+    if tarball_path.is_file() {
+        return file::size(tarball_path).ok();
+    }
+    match HTTP_FETCH.head(url).await {
+        Ok(res) => res
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|l| l.to_str().ok()?.parse::<u64>().ok()),
+        Err(err) => {
+            debug!("failed to get the size of {url}: {err:#}");
+            None
+        }
+    }
+}
+
+fn strip_minimal(install_path: &Path) -> Result<()> {
+    for dir in MINIMAL_STRIP {
+        file::remove_all(install_path.join(dir))?;
+    }
+    Ok(())
+}
+
+/// how a downloaded tarball is verified, set with the `verify` tool option
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verify {
+    /// the minisign signature published next to the tarball
+    Minisign,
+    /// only the checksum in mise.lock, for mirrors that do not publish signatures
+    Checksum,
+}
+
+/// errors if `zig version` reported something other than the version that was installed, e.g.
+/// when a mirror served the wrong archive. named builds (`ref:master`) and mach versions like
+/// `2024.5.0-mach` report a dev version that isn't known up front so they are not checked, and
+/// forks (`repo=`) are skipped by the caller since their release tags aren't zig versions.
+fn check_reported_version(tv: &ToolVersion, reported: &str) -> Result<()> {
+    if tv.version.starts_with("ref:") || tv.version.ends_with("-mach") {
+        return Ok(());
+    }
+    let reported = reported.trim();
+    if reported != tv.version {
+        bail!(
+            "{} reported version {reported}, expected {}",
+            display_path(tv.install_path()),
+            tv.version
+        );
+    }
+    Ok(())
+}
+
+fn verify_mode(tv: &ToolVersion) -> Result<Verify> {
+    match tv.request.options().get("verify").map(|v| v.as_str()) {
+        None | Some("minisign") => Ok(Verify::Minisign),
+        Some("checksum") => Ok(Verify::Checksum),
+        Some(v) => bail!("invalid zig verify option: {v}, expected minisign or checksum"),
+    }
+}
+
+fn require_checksum(tv: &ToolVersion, filename: &str) -> Result<()> {
+    if !tv.checksums.contains_key(filename) {
+        bail!("verify=checksum requires a checksum for {filename} in mise.lock");
+    }
+    Ok(())
+}
+
+/// downloads the tarball from the first mirror that works and checks its signature unless
+/// only a checksum is used. returns the url and how the tarball was verified.
+async fn download_verified(
+    urls: &[String],
+    tarball_path: &Path,
+    verify: Verify,
+    tofu: bool,
+    pr: &Box<dyn SingleReport>,
+) -> Result<(String, Option<&'static str>)> {
+    let filename = tarball_path.file_name().unwrap().to_string_lossy();
+    try_mirrors(urls, |url| {
+        let filename = &filename;
+        async move {
+            pr.set_message(format!("download {filename}"));
+            HTTP.download_file(&url, tarball_path, Some(pr)).await?;
+            if verify == Verify::Checksum {
+                return Ok((url, None));
+            }
+
+            // the signature is checked against the zig key so a mirror cannot tamper with it
+            pr.set_message(format!("minisign {filename}"));
+            let sig = match HTTP.get_text(format!("{url}.minisig")).await {
+                Ok(sig) => sig,
+                Err(err) if tofu => {
+                    debug!("no minisign signature for {url}, using tofu: {err:#}");
+                    pr.set_message(format!("tofu {filename}"));
+                    tofu_check(&dirs::STATE.join("tofu/zig"), filename, tarball_path)?;
+                    return Ok((url, Some("tofu:sha256")));
+                }
+                Err(err) => return Err(err),
+            };
+            let tarball_data = file::read(tarball_path)?;
+            minisign::verify(ZIG_MINISIGN_KEY, &tarball_data, &sig)?;
+            Ok((url, Some("minisign:verified")))
+        }
+    })
+    .await
+}
+
+/// runs `fetch` once per `key`, concurrent callers wait for the first one. errors are not kept so
+/// a later call tries again
+async fn fetch_once<T, F, Fut>(
+    cache: &DashMap<String, Arc<OnceCell<T>>>,
+    key: String,
+    fetch: F,
+) -> Result<T>
+where
+    T: Clone,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let cell = cache.entry(key).or_default().clone();
+    Ok(cell.get_or_try_init(fetch).await?.clone())
+}
+
+async fn list_release_versions(api_url: &str, repo: &str) -> Result<Vec<String>> {
+    let versions = github::list_releases_from_url(api_url, repo)
+        .await?
+        .into_iter()
+        .map(|r| r.tag_name)
+        .unique()
+        .sorted_by_cached_key(|s| version_sort_key(s))
+        .collect();
+    Ok(versions)
+}
+
+/// dev builds like `0.14.0-dev.123+abc` order by their dev counter since the `+hash` says nothing
+/// about which is newer, everything else orders by Versioning
+fn version_sort_key(version: &str) -> (Option<Versioning>, Option<u64>, String) {
+    let dev = regex!(r"^(.+-dev)\.([0-9]+)(\+.*)?$");
+    if let Some(c) = dev.captures(version) {
+        if let Ok(n) = c[2].parse() {
+            return (Versioning::new(&c[1]), Some(n), version.to_string());
+        }
+    }
+    (Versioning::new(version), None, version.to_string())
+}
+
+/// forks may name their assets `zig-<os>-<arch>-<version>` or `zig-<arch>-<os>-<version>`
+fn fork_asset<'a>(
+    assets: &'a [github::GithubAsset],
+    os: &str,
+    arch: &str,
+    archive_ext: &str,
+) -> Option<&'a github::GithubAsset> {
+    let prefixes = [format!("zig-{os}-{arch}-"), format!("zig-{arch}-{os}-")];
+    assets.iter().find(|a| {
+        a.name.ends_with(&format!(".{archive_ext}"))
+            && prefixes.iter().any(|p| a.name.starts_with(p))
+    })
+}
+
+fn archive_ext() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "zip"
+    } else {
+        "tar.xz"
+    }
+}
+
+/// https://ziglang.org or the configured zig.mirror_url
+fn ziglang_base_url(settings: &Settings) -> String {
+    settings
+        .zig
+        .mirror_url
+        .as_deref()
+        .unwrap_or("https://ziglang.org")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// candidate urls for a file hosted on ziglang.org, trying each of zig.mirrors first
+fn ziglang_urls(settings: &Settings, path: &str) -> Vec<String> {
+    settings
+        .zig
+        .mirrors
+        .iter()
+        .map(|m| m.trim_end_matches('/').to_string())
+        .chain([ziglang_base_url(settings)])
+        .map(|base| format!("{base}/{path}"))
+        .unique()
+        .collect()
+}
+
+/// calls `f` with each url in order until one succeeds, returning the last error if none do
+async fn try_mirrors<T, F, Fut>(urls: &[String], mut f: F) -> Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut last_err = None;
+    for url in urls {
+        match f(url.clone()).await {
+            Ok(res) => return Ok(res),
+            Err(err) => {
+                debug!("zig download from {url} failed: {err:#}");
+                last_err = Some(err);
+            }
+        }
+    }
+    Err(last_err.unwrap_or_else(|| eyre!("no zig download urls")))
+}
+
+/// the release index which lists the current version for `key`
+fn index_url(settings: &Settings, key: &str) -> Result<String> {
+    match key {
+        "master" => Ok(format!(
+            "{}/download/index.json",
+            ziglang_base_url(settings)
+        )),
+        "mach-latest" => Ok("https://machengine.org/zig/index.json".to_string()),
+        _ => bail!("no zig version index for {key}"),
+    }
+}
+
+/// trust-on-first-use: the first archive seen for `filename` has its sha256 recorded in `store` and
+/// every later download of it must match
+fn tofu_check(store: &Path, filename: &str, tarball_path: &Path) -> Result<()> {
+    let checksum = hash::file_hash_sha256(tarball_path, None)?;
+    let record = store.join(format!("{filename}.sha256"));
+    if record.exists() {
+        let expected = file::read_to_string(&record)?;
+        if expected.trim() != checksum {
+            bail!(
+                "tofu checksum mismatch for {filename}: expected sha256:{}, got sha256:{checksum}\n\
+                 remove {} if the change is expected",
+                expected.trim(),
+                display_path(&record)
+            );
+        }
+    } else {
+        file::create_dir_all(store)?;
+        file::write(&record, &checksum)?;
+    }
+    Ok(())
+}
+
+/// the version a named build like `master` or `mach-latest` points to in a zig index.json
+fn version_from_index(index: &serde_json::Value, key: &str) -> Option<String> {
+    index
+        .pointer(&format!("/{key}/version"))
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+fn shasum_from_index(index: &serde_json::Value, key: &str, target: &str) -> Option<String> {
+    index
+        .get(key)
+        .and_then(|v| v.get(target))
+        .and_then(|t| t.get("shasum"))
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+/// checks the tarball against the sha256 from the version index. with no checksum in the lockfile
+/// yet the shasum becomes the one verify_checksum checks and records, otherwise both are checked.
+fn check_index_shasum(
+    ctx: &InstallContext,
+    tv: &mut ToolVersion,
+    tarball_path: &Path,
+    shasum: &str,
+) -> Result<()> {
+    let filename = tarball_path
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+    let checksum = format!("sha256:{shasum}");
+    match tv.checksums.get(&filename) {
+        None => {
+            tv.checksums.insert(filename, checksum);
+        }
+        Some(locked) if *locked != checksum => {
+            ctx.pr.set_message(format!("shasum {filename}"));
+            hash::ensure_checksum(tarball_path, shasum, Some(&ctx.pr), "sha256")?;
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+/// with zig.manage_cache, keeps zig's caches under the mise cache dir for this version
+fn cache_env(settings: &Settings, tv: &ToolVersion) -> EnvMap {
+    let mut env = EnvMap::new();
+    if settings.zig.manage_cache {
+        let cache = tv.cache_path();
+        env.insert(
+            "ZIG_GLOBAL_CACHE_DIR".into(),
+            cache.join("global").to_string_lossy().to_string(),
+        );
+        env.insert(
+            "ZIG_LOCAL_CACHE_DIR".into(),
+            cache.join("local").to_string_lossy().to_string(),
+        );
+    }
+    env
 }
 
 fn os() -> &'static str {
@@ -215,3 +803,594 @@ fn arch() -> &'static str {
         arch
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::toolset::{ToolRequest, ToolSource};
+    use crate::ui::progress_report::QuietReport;
+
+    #[test]
+    fn test_index_url() {
+        let mut settings = Settings::get().deref().clone();
+        settings.zig.mirror_url = None;
+        assert_eq!(
+            index_url(&settings, "master").unwrap(),
+            "https://ziglang.org/download/index.json"
+        );
+        settings.zig.mirror_url = Some("https://zig.example.com/mirror/".into());
+        assert_eq!(
+            index_url(&settings, "master").unwrap(),
+            "https://zig.example.com/mirror/download/index.json"
+        );
+        assert_eq!(
+            index_url(&settings, "mach-latest").unwrap(),
+            "https://machengine.org/zig/index.json"
+        );
+        assert!(index_url(&settings, "nightly").is_err());
+    }
+
+    #[test]
+    fn test_ziglang_urls() {
+        let mut settings = Settings::get().deref().clone();
+        settings.zig.mirror_url = None;
+        settings.zig.mirrors = vec!["https://a.example.com/zig/".into()];
+        assert_eq!(
+            ziglang_urls(&settings, "download/index.json"),
+            vec![
+                "https://a.example.com/zig/download/index.json",
+                "https://ziglang.org/download/index.json",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_try_mirrors() {
+        let mut server = mockito::Server::new_async().await;
+        let missing = server
+            .mock("GET", "/a/zig.tar.xz")
+            .with_status(404)
+            .create_async()
+            .await;
+        let found = server
+            .mock("GET", "/b/zig.tar.xz")
+            .with_status(200)
+            .with_body("zig")
+            .create_async()
+            .await;
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("zig.tar.xz");
+        let urls = ["a", "b"].map(|m| format!("{}/{m}/zig.tar.xz", server.url()));
+        let url = try_mirrors(&urls, |url| {
+            let path = &path;
+            async move {
+                HTTP.download_file(&url, path, None).await?;
+                Ok(url)
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(url, urls[1]);
+        assert_eq!(file::read_to_string(&path).unwrap(), "zig");
+        missing.assert_async().await;
+        found.assert_async().await;
+
+        // when every mirror fails the error from the last one is returned
+        let err = try_mirrors(
+            &urls,
+            |url| async move { Err::<(), _>(eyre!("{url} failed")) },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), format!("{} failed", urls[1]));
+    }
+
+    #[tokio::test]
+    async fn test_list_release_versions_from_repo() {
+        let mut server = mockito::Server::new_async().await;
+        let repo = unique_repo("myorg");
+        let releases = server
+            .mock("GET", format!("/repos/{repo}/releases").as_str())
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    {"tag_name": "0.14.0-fork.2", "draft": false, "prerelease": false, "assets": []},
+                    {"tag_name": "0.13.0-fork.1", "draft": false, "prerelease": false, "assets": []},
+                    {"tag_name": "0.15.0-wip", "draft": true, "prerelease": false, "assets": []},
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let versions = list_release_versions(&server.url(), &repo).await.unwrap();
+        assert_eq!(versions, vec!["0.13.0-fork.1", "0.14.0-fork.2"]);
+        releases.assert_async().await;
+    }
+
+    /// a repo name no other test run has used, since github releases are also cached on disk by
+    /// api url and repo and mockito reuses ports
+    fn unique_repo(owner: &str) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        format!("{owner}-{nanos}/zig")
+    }
+
+    #[tokio::test]
+    async fn test_release_versions_fetched_once() {
+        let mut server = mockito::Server::new_async().await;
+        let repo = unique_repo("ziglang");
+        let releases = server
+            .mock("GET", format!("/repos/{repo}/releases").as_str())
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    {"tag_name": "0.13.0", "draft": false, "prerelease": false, "assets": []},
+                    {"tag_name": "0.12.0", "draft": false, "prerelease": false, "assets": []},
+                    {"tag_name": "0.11.0", "draft": false, "prerelease": false, "assets": []},
+                ])
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let zig = ZigPlugin::new();
+        let url = server.url();
+        let (a, b, c) = tokio::join!(
+            zig.release_versions(&url, &repo),
+            zig.release_versions(&url, &repo),
+            zig.release_versions(&url, &repo),
+        );
+        for versions in [a, b, c] {
+            assert_eq!(versions.unwrap(), vec!["0.11.0", "0.12.0", "0.13.0"]);
+        }
+        releases.assert_async().await;
+        // all three shared the one in-process entry
+        assert_eq!(zig.releases.len(), 1);
+        let cell = zig.releases.get(&format!("{url}/repos/{repo}")).unwrap();
+        assert_eq!(
+            cell.get().cloned(),
+            Some(vec!["0.11.0".into(), "0.12.0".into(), "0.13.0".into()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_named_build_frozen() {
+        let mut server = mockito::Server::new_async().await;
+        let index = |version: &str| serde_json::json!({"master": {"version": version}}).to_string();
+        let first = server
+            .mock("GET", "/index.json")
+            .with_status(200)
+            .with_body(index("0.15.0-dev.1+aaaaaaa"))
+            .expect(1)
+            .create_async()
+            .await;
+        let url = format!("{}/index.json", server.url());
+        let builds = DashMap::new();
+        let a = ZigPlugin::new()
+            .named_build_version(&builds, &url, "master")
+            .await
+            .unwrap();
+        first.remove_async().await;
+        // master moved on, but a fresh plugin in the same session still gets the first build
+        let _moved = server
+            .mock("GET", "/index.json")
+            .with_status(200)
+            .with_body(index("0.15.0-dev.2+bbbbbbb"))
+            .create_async()
+            .await;
+        let b = ZigPlugin::new()
+            .named_build_version(&builds, &url, "master")
+            .await
+            .unwrap();
+        assert_eq!(a, "0.15.0-dev.1+aaaaaaa");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_install_atomic() {
+        let mut settings = Settings::get().deref().clone();
+        let tmp = tempfile::tempdir().unwrap();
+        let install_path = tmp.path().join("installs/zig/0.13.0");
+        file::create_dir_all(&install_path).unwrap();
+        file::write(install_path.join("zig"), "old").unwrap();
+
+        let err = install_atomic(&settings, &install_path, |dir| {
+            file::write(dir.join("zig"), "partial")?;
+            bail!("extract failed")
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "extract failed");
+        assert_eq!(file::ls(&install_path).unwrap().len(), 1);
+        assert_eq!(
+            file::read_to_string(install_path.join("zig")).unwrap(),
+            "old"
+        );
+        // the temp dir is cleaned up
+        assert_eq!(file::ls(install_path.parent().unwrap()).unwrap().len(), 1);
+
+        // a rename that fails, like one across filesystems, puts the old install back
+        let err = install_atomic(&settings, &install_path, |dir| {
+            file::write(dir.join("zig"), "new")?;
+            file::remove_all(dir)
+        })
+        .unwrap_err();
+        assert!(err.to_string().starts_with("failed rename"), "{err}");
+        assert_eq!(
+            file::read_to_string(install_path.join("zig")).unwrap(),
+            "old"
+        );
+        assert_eq!(file::ls(install_path.parent().unwrap()).unwrap().len(), 1);
+
+        settings.zig.extract_dir = Some(tmp.path().join("extract"));
+        install_atomic(&settings, &install_path, |dir| {
+            file::write(dir.join("zig"), "new")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            file::read_to_string(install_path.join("zig")).unwrap(),
+            "new"
+        );
+        assert!(file::ls(&tmp.path().join("extract")).unwrap().is_empty());
+        assert_eq!(file::ls(install_path.parent().unwrap()).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tarball_size() {
+        let mut server = mockito::Server::new_async().await;
+        let head = server
+            .mock("HEAD", "/zig.tar.xz")
+            .with_header("content-length", "1000")
+            .create_async()
+            .await;
+        let missing = server
+            .mock("HEAD", "/missing.tar.xz")
+            .with_status(500)
+            .create_async()
+            .await;
+        let tmp = tempfile::tempdir().unwrap();
+        let tarball = tmp.path().join("zig.tar.xz");
+        let url = format!("{}/zig.tar.xz", server.url());
+        assert_eq!(tarball_size(&tarball, &url).await, Some(1000));
+        head.assert_async().await;
+
+        // an already downloaded tarball is not requested again
+        file::write(&tarball, "tarball").unwrap();
+        assert_eq!(tarball_size(&tarball, &url).await, Some(7));
+        head.assert_async().await;
+
+        let url = format!("{}/missing.tar.xz", server.url());
+        let tarball = tmp.path().join("missing.tar.xz");
+        assert_eq!(tarball_size(&tarball, &url).await, None);
+        missing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_named_build_outdated() {
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let tr = ToolRequest::new(ba, "ref:master", ToolSource::Argument).unwrap();
+        let mut tv = ToolVersion::new(tr, "ref:master".into());
+        let tmp = tempfile::tempdir().unwrap();
+        tv.install_path = Some(tmp.path().to_path_buf());
+        let receipt = |resolved_version: &str| {
+            let recorded = SbomEntry {
+                resolved_version: Some(resolved_version.into()),
+                ..Default::default()
+            };
+            sbom::write_receipt(&tv, &SbomEntry::from_install(&tv, Some(recorded))).unwrap();
+        };
+        receipt("0.15.0-dev.9+aaaaaaa");
+        // what ziglang.org's index points to for the rest of this session
+        let builds = DashMap::new();
+        builds.insert(
+            "master".to_string(),
+            Arc::new(OnceCell::new_with(Some("0.15.0-dev.10+bbbbbbb".into()))),
+        );
+        let zig = ZigPlugin::new();
+        let oi = zig
+            .named_build_outdated(&builds, &tv)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(oi.current.as_deref(), Some("0.15.0-dev.9+aaaaaaa"));
+        assert_eq!(oi.latest, "0.15.0-dev.10+bbbbbbb");
+
+        receipt("0.15.0-dev.10+bbbbbbb");
+        assert!(
+            zig.named_build_outdated(&builds, &tv)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_version_sort_key() {
+        let versions = [
+            "0.14.0",
+            "0.14.0-dev.123+aaaaaaa",
+            "0.13.0",
+            "0.14.0-dev.99+fffffff",
+            "0.14.0-dev.1000+0000000",
+        ];
+        assert_eq!(
+            versions
+                .into_iter()
+                .sorted_by_cached_key(|v| version_sort_key(v))
+                .collect_vec(),
+            vec![
+                "0.13.0",
+                "0.14.0-dev.99+fffffff",
+                "0.14.0-dev.123+aaaaaaa",
+                "0.14.0-dev.1000+0000000",
+                "0.14.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fork_asset() {
+        let asset = |name: &str| github::GithubAsset {
+            name: name.to_string(),
+            browser_download_url: format!("https://example.com/{name}"),
+        };
+        let assets = [
+            asset("zig-linux-x86_64-0.14.0-fork.2.tar.xz.minisig"),
+            asset("zig-macos-aarch64-0.14.0-fork.2.tar.xz"),
+            asset("zig-x86_64-linux-0.14.0-fork.2.tar.xz"),
+        ];
+        let found = |os, arch| fork_asset(&assets, os, arch, "tar.xz").map(|a| a.name.as_str());
+        assert_eq!(
+            found("linux", "x86_64"),
+            Some("zig-x86_64-linux-0.14.0-fork.2.tar.xz")
+        );
+        assert_eq!(
+            found("macos", "aarch64"),
+            Some("zig-macos-aarch64-0.14.0-fork.2.tar.xz")
+        );
+        assert_eq!(found("windows", "x86_64"), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_strip_minimal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let install_path = tmp.path().join("zig");
+        for dir in ["doc", "lib/std", "bin"] {
+            file::create_dir_all(install_path.join(dir)).unwrap();
+        }
+        file::write(install_path.join("doc/langref.html"), "").unwrap();
+        file::write(install_path.join("lib/std/std.zig"), "").unwrap();
+        file::write(install_path.join("zig"), "#!/bin/sh\necho 0.13.0\n").unwrap();
+        file::make_executable(install_path.join("zig")).unwrap();
+        file::make_symlink(Path::new("../zig"), &install_path.join("bin/zig")).unwrap();
+
+        strip_minimal(&install_path).unwrap();
+        assert!(!install_path.join("doc").exists());
+        assert!(install_path.join("lib/std/std.zig").exists());
+
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let mut tr = ToolRequest::new(ba, "0.13.0", ToolSource::Argument).unwrap();
+        tr.set_options(crate::toolset::parse_tool_options(&format!(
+            "minimal=true,install_path={}",
+            install_path.display()
+        )));
+        let tv = ToolVersion::new(tr, "0.13.0".into());
+        let ctx = InstallContext {
+            ts: Arc::new(Toolset::default()),
+            pr: Box::new(QuietReport::new()),
+            force: false,
+            sbom: Default::default(),
+        };
+        ZigPlugin::new().verify(&ctx, &tv).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_verify_version_mismatch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let install_path = tmp.path().join("zig");
+        file::create_dir_all(install_path.join("bin")).unwrap();
+        file::write(install_path.join("bin/zig"), "#!/bin/sh\necho 0.11.0\n").unwrap();
+        file::make_executable(install_path.join("bin/zig")).unwrap();
+        let ctx = InstallContext {
+            ts: Arc::new(Toolset::default()),
+            pr: Box::new(QuietReport::new()),
+            force: false,
+            sbom: Default::default(),
+        };
+        let tv_with = |version: &str, opts: &str| {
+            let ba: Arc<BackendArg> = Arc::new("zig".into());
+            let mut tr = ToolRequest::new(ba, version, ToolSource::Argument).unwrap();
+            tr.set_options(crate::toolset::parse_tool_options(&format!(
+                "install_path={}{opts}",
+                install_path.display()
+            )));
+            ToolVersion::new(tr, version.into())
+        };
+        let tv = |version: &str| tv_with(version, "");
+        let zig = ZigPlugin::new();
+        zig.verify(&ctx, &tv("0.11.0")).unwrap();
+        let err = zig.verify(&ctx, &tv("0.12.0")).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("reported version 0.11.0, expected 0.12.0"),
+            "{err}"
+        );
+        // named builds resolve to a dev version that isn't known until download
+        zig.verify(&ctx, &tv("ref:master")).unwrap();
+        // fork release tags are not the version the fork's zig reports
+        zig.verify(&ctx, &tv_with("0.14.0-fork.2", ",repo=myorg/zig"))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_checksum_mode() {
+        let mut server = mockito::Server::new_async().await;
+        let tarball = server
+            .mock("GET", "/zig.tar.xz")
+            .with_status(200)
+            .with_body("zig")
+            .expect(2)
+            .create_async()
+            .await;
+        let minisig = server
+            .mock("GET", "/zig.tar.xz.minisig")
+            .with_status(404)
+            .create_async()
+            .await;
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("zig.tar.xz");
+        let urls = [format!("{}/zig.tar.xz", server.url())];
+        let pr: Box<dyn SingleReport> = Box::new(QuietReport::new());
+
+        let (url, signature) = download_verified(&urls, &path, Verify::Checksum, false, &pr)
+            .await
+            .unwrap();
+        assert_eq!(url, urls[0]);
+        assert_eq!(signature, None);
+        assert!(!minisig.matched_async().await);
+
+        // without verify=checksum a missing signature fails the download
+        assert!(
+            download_verified(&urls, &path, Verify::Minisign, false, &pr)
+                .await
+                .is_err()
+        );
+        tarball.assert_async().await;
+
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let mut tr = ToolRequest::new(ba, "0.13.0", ToolSource::Argument).unwrap();
+        tr.set_options(crate::toolset::parse_tool_options("verify=checksum"));
+        let mut tv = ToolVersion::new(tr, "0.13.0".into());
+        assert_eq!(verify_mode(&tv).unwrap(), Verify::Checksum);
+        let err = require_checksum(&tv, "zig.tar.xz").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "verify=checksum requires a checksum for zig.tar.xz in mise.lock"
+        );
+        tv.checksums.insert(
+            "zig.tar.xz".into(),
+            format!("sha256:{}", hash::file_hash_sha256(&path, None).unwrap()),
+        );
+        require_checksum(&tv, "zig.tar.xz").unwrap();
+    }
+
+    #[test]
+    fn test_version_from_index() {
+        let index = serde_json::json!({
+            "mach-latest": {
+                "version": "0.14.0-dev.2577+271452d22",
+                "date": "2024-12-30",
+            },
+            "2024.11.0-mach": {
+                "version": "0.14.0-dev.2577+271452d22",
+            },
+        });
+        assert_eq!(
+            version_from_index(&index, "mach-latest").as_deref(),
+            Some("0.14.0-dev.2577+271452d22")
+        );
+        assert_eq!(version_from_index(&index, "master"), None);
+    }
+
+    #[test]
+    fn test_shasum_from_index() {
+        let index = serde_json::json!({
+            "0.13.0": {
+                "x86_64-linux": {"tarball": "https://ziglang.org/zig.tar.xz", "shasum": "abc"},
+            },
+            "2024.11.0-mach": {
+                "x86_64-linux": {"tarball": "https://pkg.machengine.org/zig.tar.xz"},
+            },
+        });
+        assert_eq!(
+            shasum_from_index(&index, "0.13.0", "x86_64-linux").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(shasum_from_index(&index, "0.13.0", "aarch64-macos"), None);
+        assert_eq!(
+            shasum_from_index(&index, "2024.11.0-mach", "x86_64-linux"),
+            None
+        );
+        assert_eq!(shasum_from_index(&index, "0.12.0", "x86_64-linux"), None);
+    }
+
+    #[test]
+    fn test_check_index_shasum() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tarball = tmp.path().join("zig.tar.xz");
+        file::write(&tarball, "zig").unwrap();
+        let sha256 = hash::file_hash_sha256(&tarball, None).unwrap();
+        let ctx = InstallContext {
+            ts: Arc::new(Toolset::default()),
+            pr: Box::new(QuietReport::new()),
+            force: false,
+            sbom: Default::default(),
+        };
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let tr = ToolRequest::new(ba, "0.13.0", ToolSource::Argument).unwrap();
+        let mut tv = ToolVersion::new(tr, "0.13.0".into());
+
+        // with nothing locked the index shasum is what verify_checksum checks and records
+        check_index_shasum(&ctx, &mut tv, &tarball, &sha256).unwrap();
+        assert_eq!(tv.checksums["zig.tar.xz"], format!("sha256:{sha256}"));
+        let err = check_index_shasum(&ctx, &mut tv, &tarball, "bad");
+        assert!(err.is_err());
+
+        // a locked checksum in another format does not skip the index shasum
+        tv.checksums
+            .insert("zig.tar.xz".into(), "blake3:locked".into());
+        check_index_shasum(&ctx, &mut tv, &tarball, &sha256).unwrap();
+        assert!(check_index_shasum(&ctx, &mut tv, &tarball, "bad").is_err());
+    }
+
+    #[test]
+    fn test_tofu_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = tmp.path().join("tofu");
+        let tarball = tmp.path().join("zig.tar.xz");
+        file::write(&tarball, "zig").unwrap();
+        tofu_check(&store, "zig.tar.xz", &tarball).unwrap();
+        tofu_check(&store, "zig.tar.xz", &tarball).unwrap();
+
+        file::write(&tarball, "tampered").unwrap();
+        let err = tofu_check(&store, "zig.tar.xz", &tarball).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("tofu checksum mismatch for zig.tar.xz"),
+            "{err}"
+        );
+        // other archives are recorded separately
+        tofu_check(&store, "zig-other.tar.xz", &tarball).unwrap();
+    }
+
+    #[test]
+    fn test_cache_env() {
+        let plugin = ZigPlugin::new();
+        let tr = ToolRequest::new(plugin.ba.clone(), "0.13.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "0.13.0".into());
+        let mut settings = Settings::get().deref().clone();
+
+        settings.zig.manage_cache = false;
+        assert!(cache_env(&settings, &tv).is_empty());
+
+        settings.zig.manage_cache = true;
+        let env = cache_env(&settings, &tv);
+        let cache = tv.cache_path();
+        assert_eq!(
+            env.get("ZIG_GLOBAL_CACHE_DIR").unwrap(),
+            &cache.join("global").to_string_lossy()
+        );
+        assert_eq!(
+            env.get("ZIG_LOCAL_CACHE_DIR").unwrap(),
+            &cache.join("local").to_string_lossy()
+        );
+    }
+}
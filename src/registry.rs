@@ -150,6 +150,16 @@ pub fn tool_enabled<T: Ord>(
     }
 }
 
+/// the registry tool whose short is closest to `short`, used for "did you mean" hints on typos
+pub fn suggest_short(short: &str) -> Option<&'static str> {
+    REGISTRY
+        .keys()
+        .map(|k| (*k, strsim::jaro_winkler(short, k)))
+        .filter(|(_, score)| *score >= 0.85)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(k, _)| k)
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -169,4 +179,13 @@ mod tests {
             &name
         ));
     }
+
+    #[test]
+    fn test_suggest_short() {
+        use super::*;
+
+        assert_eq!(suggest_short("nod"), Some("node"));
+        assert_eq!(suggest_short("pyhton"), Some("python"));
+        assert_eq!(suggest_short("zzzzzzzz"), None);
+    }
 }
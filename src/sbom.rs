@@ -0,0 +1,174 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use eyre::Result;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::file;
+use crate::toolset::ToolVersion;
+
+/// a single line in the SBOM file describing where an installed tool came from
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SbomEntry {
+    pub tool: String,
+    pub version: String,
+    /// the concrete version a named build like `ref:mach-latest` resolved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl SbomEntry {
+    /// builds an entry from what the backend recorded during install, falling back to the
+    /// tool name, version, and any checksum that was verified
+    pub fn from_install(tv: &ToolVersion, recorded: Option<SbomEntry>) -> Self {
+        let mut entry = recorded.unwrap_or_default();
+        entry.tool = tv.ba().full();
+        entry.version = tv.version.clone();
+        if entry.checksum.is_none() {
+            entry.checksum = tv.checksums.values().next().cloned();
+        }
+        entry
+    }
+}
+
+/// writes one JSON line per installed tool version to `path`, replacing the line of an earlier
+/// install of the same tool@version
+pub fn record(path: &Path, entry: &SbomEntry) -> Result<()> {
+    // installs run in parallel and each one rewrites the file
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _lock = LOCK.lock().unwrap();
+    if let Some(parent) = path.parent() {
+        file::create_dir_all(parent)?;
+    }
+    let mut lines = vec![];
+    if path.exists() {
+        for line in file::read_to_string(path)?.lines() {
+            let existing: SbomEntry = serde_json::from_str(line)?;
+            if existing.tool != entry.tool || existing.version != entry.version {
+                lines.push(line.to_string());
+            }
+        }
+    }
+    lines.push(serde_json::to_string(entry)?);
+    file::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+pub const RECEIPT_FILENAME: &str = ".mise-receipt.json";
+
+/// written into the install dir of every tool version so audits can see what was installed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    #[serde(flatten)]
+    pub entry: SbomEntry,
+    pub installed_at: String,
+}
+
+pub fn write_receipt(tv: &ToolVersion, entry: &SbomEntry) -> Result<()> {
+    let receipt = Receipt {
+        entry: entry.clone(),
+        installed_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let path = tv.install_path().join(RECEIPT_FILENAME);
+    file::write(&path, serde_json::to_string_pretty(&receipt)?)?;
+    Ok(())
+}
+
+/// the receipt in `install_path`, if the version was installed by a mise which writes them
+pub fn read_receipt(install_path: &Path) -> Result<Option<Receipt>> {
+    let path = install_path.join(RECEIPT_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&file::read_to_string(&path)?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::cli::args::BackendArg;
+    use crate::toolset::{ToolRequest, ToolSource};
+
+    #[test]
+    fn test_record() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("sbom.jsonl");
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let tr = ToolRequest::new(ba, "0.13.0", ToolSource::Argument).unwrap();
+        let mut tv = ToolVersion::new(tr, "0.13.0".into());
+        tv.checksums
+            .insert("zig-linux-x86_64-0.13.0.tar.xz".into(), "sha256:abc".into());
+        let entry = SbomEntry::from_install(
+            &tv,
+            Some(SbomEntry {
+                url: Some(
+                    "https://ziglang.org/download/0.13.0/zig-linux-x86_64-0.13.0.tar.xz".into(),
+                ),
+                signature: Some("minisign:verified".into()),
+                ..Default::default()
+            }),
+        );
+        record(&path, &entry).unwrap();
+        let other = SbomEntry {
+            version: "0.14.0".into(),
+            ..entry.clone()
+        };
+        record(&path, &other).unwrap();
+        // recording the same tool@version again replaces its entry
+        record(&path, &entry).unwrap();
+
+        let contents = file::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(serde_json::from_str::<SbomEntry>(lines[0]).unwrap(), other);
+        let entry: SbomEntry = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(
+            entry,
+            SbomEntry {
+                tool: "core:zig".into(),
+                version: "0.13.0".into(),
+                resolved_version: None,
+                url: Some(
+                    "https://ziglang.org/download/0.13.0/zig-linux-x86_64-0.13.0.tar.xz".into()
+                ),
+                checksum: Some("sha256:abc".into()),
+                signature: Some("minisign:verified".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_receipt() {
+        let ba: Arc<BackendArg> = Arc::new("zig".into());
+        let tr = ToolRequest::new(ba, "0.13.0", ToolSource::Argument).unwrap();
+        let mut tv = ToolVersion::new(tr, "0.13.0".into());
+        let tmp = tempfile::tempdir().unwrap();
+        tv.install_path = Some(tmp.path().to_path_buf());
+        assert_eq!(read_receipt(&tv.install_path()).unwrap(), None);
+
+        let entry = SbomEntry::from_install(
+            &tv,
+            Some(SbomEntry {
+                url: Some(
+                    "https://ziglang.org/download/0.13.0/zig-linux-x86_64-0.13.0.tar.xz".into(),
+                ),
+                signature: Some("minisign:verified".into()),
+                ..Default::default()
+            }),
+        );
+        write_receipt(&tv, &entry).unwrap();
+        let receipt = read_receipt(&tv.install_path()).unwrap().unwrap();
+        assert_eq!(receipt.entry, entry);
+        assert!(chrono::DateTime::parse_from_rfc3339(&receipt.installed_at).is_ok());
+    }
+}
@@ -80,7 +80,7 @@ where
             .unwrap_or_default()
             .filter_by_tool(["sops".into()].into())
             .into_toolset();
-        Box::pin(ts.resolve()).await?;
+        Box::pin(ts.resolve(false)).await?;
         let sops = ts
             .which_bin("sops")
             .await
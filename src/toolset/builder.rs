@@ -51,7 +51,7 @@ impl ToolsetBuilder {
             self.load_runtime_args(&mut toolset)?;
         });
         measure!("toolset_builder::build::resolve", {
-            if let Err(err) = toolset.resolve().await {
+            if let Err(err) = toolset.resolve(false).await {
                 if Error::is_argument_err(&err) {
                     return Err(err);
                 }
@@ -0,0 +1,149 @@
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde_json::json;
+
+use crate::toolset::ToolVersion;
+use crate::ui::progress_report::SingleReport;
+
+/// where install progress is reported besides the progress UI
+#[derive(Clone, Default)]
+pub enum InstallReporter {
+    /// only the progress UI
+    #[default]
+    Tty,
+    /// also newline-delimited json events for editors and other tooling
+    Json(Arc<Mutex<dyn Write + Send>>),
+}
+
+/// what each tool version installed with some [super::InstallOptions] produced. clones share the
+/// same records.
+#[derive(Debug, Clone, Default)]
+pub struct InstallRecords(Arc<DashMap<ToolVersion, InstallRecord>>);
+
+/// what installing a tool version produced besides the installed version itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstallRecord {
+    /// wall time of the install including retries
+    pub duration: Duration,
+    /// output of the install, only captured when `InstallOptions::capture_logs` is set
+    pub log: Vec<String>,
+}
+
+impl Debug for InstallReporter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tty => write!(f, "Tty"),
+            Self::Json(_) => write!(f, "Json"),
+        }
+    }
+}
+
+impl InstallReporter {
+    pub fn start(&self, tv: &ToolVersion, start: Instant) {
+        self.emit(tv, start, json!({"event": "start"}));
+    }
+
+    pub fn success(&self, tv: &ToolVersion, start: Instant) {
+        self.emit(tv, start, json!({"event": "success"}));
+    }
+
+    pub fn failure(&self, tv: &ToolVersion, start: Instant, err: &eyre::Report) {
+        self.emit(
+            tv,
+            start,
+            json!({"event": "failure", "error": format!("{err:#}")}),
+        );
+    }
+
+    /// wraps `pr` so its messages are also emitted as progress events
+    pub fn report(
+        &self,
+        tv: &ToolVersion,
+        start: Instant,
+        pr: Box<dyn SingleReport>,
+    ) -> Box<dyn SingleReport> {
+        match self {
+            Self::Tty => pr,
+            Self::Json(_) => Box::new(JsonReport {
+                inner: pr,
+                reporter: self.clone(),
+                tv: tv.clone(),
+                start,
+            }),
+        }
+    }
+
+    fn emit(&self, tv: &ToolVersion, start: Instant, mut event: serde_json::Value) {
+        let Self::Json(sink) = self else {
+            return;
+        };
+        event["tool"] = json!(tv.ba().short);
+        event["version"] = json!(tv.version);
+        event["elapsed_ms"] = json!(start.elapsed().as_millis() as u64);
+        let mut sink = sink.lock().unwrap();
+        if let Err(err) = writeln!(sink, "{event}").and_then(|_| sink.flush()) {
+            warn!("failed to write install event: {err}");
+        }
+    }
+}
+
+impl InstallRecords {
+    /// what installing `tv` produced, `None` unless it was installed with these records
+    pub fn get(&self, tv: &ToolVersion) -> Option<InstallRecord> {
+        self.0.get(tv).map(|r| r.clone())
+    }
+
+    pub(super) fn insert(&self, tv: &ToolVersion, record: InstallRecord) {
+        self.0.insert(tv.clone(), record);
+    }
+}
+
+struct JsonReport {
+    inner: Box<dyn SingleReport>,
+    reporter: InstallReporter,
+    tv: ToolVersion,
+    start: Instant,
+}
+
+impl JsonReport {
+    fn progress(&self, message: &str) {
+        self.reporter.emit(
+            &self.tv,
+            self.start,
+            json!({"event": "progress", "message": message}),
+        );
+    }
+}
+
+impl SingleReport for JsonReport {
+    fn println(&self, message: String) {
+        self.progress(&message);
+        self.inner.println(message);
+    }
+    fn set_message(&self, message: String) {
+        self.progress(&message);
+        self.inner.set_message(message);
+    }
+    fn inc(&self, delta: u64) {
+        self.inner.inc(delta);
+    }
+    fn set_position(&self, delta: u64) {
+        self.inner.set_position(delta);
+    }
+    fn set_length(&self, length: u64) {
+        self.inner.set_length(length);
+    }
+    fn abandon(&self) {
+        self.inner.abandon();
+    }
+    fn finish(&self) {
+        self.inner.finish();
+    }
+    fn finish_with_message(&self, message: String) {
+        self.inner.finish_with_message(message);
+    }
+}
@@ -1,27 +1,40 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock as Lazy;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::backend::Backend;
+use crate::backend::backend_type::BackendType;
 use crate::cli::args::BackendArg;
+use crate::cmd::CmdLineRunner;
 use crate::config::Config;
-use crate::config::env_directive::{EnvResolveOptions, EnvResults};
-use crate::config::settings::{SETTINGS, SettingsStatusMissingTools};
+use crate::config::env_directive::{EnvDirective, EnvResolveOptions, EnvResults};
+use crate::config::settings::{SETTINGS, Settings, SettingsStatusMissingTools};
 use crate::env::{PATH_KEY, TERM_WIDTH};
 use crate::env_diff::EnvMap;
 use crate::errors::Error;
+use crate::file::display_path;
+use crate::hash::hash_to_str;
 use crate::hooks::Hooks;
 use crate::install_context::InstallContext;
 use crate::path_env::PathEnv;
-use crate::registry::tool_enabled;
+use crate::plugins::VERSION_REGEX;
+use crate::registry::{self, tool_enabled};
+use crate::runtime_symlinks::is_runtime_symlink;
 use crate::ui::multi_progress_report::MultiProgressReport;
+use crate::ui::progress_report::{CaptureReport, SingleReport};
 use crate::uv;
-use crate::{backend, config, env, hooks};
+use crate::{backend, config, dirs, env, file, hooks, sbom, shims};
 pub use builder::ToolsetBuilder;
-use console::truncate_str;
-use eyre::{Result, WrapErr};
+use console::{style, truncate_str};
+use dashmap::DashMap;
+use eyre::{Result, WrapErr, bail};
 use indexmap::{IndexMap, IndexSet};
+pub use install_reporter::{InstallRecord, InstallRecords, InstallReporter};
 use itertools::Itertools;
 use outdated_info::OutdatedInfo;
 pub use outdated_info::is_outdated_version;
@@ -32,13 +45,16 @@ pub use tool_request_set::{ToolRequestSet, ToolRequestSetBuilder};
 pub use tool_source::ToolSource;
 pub use tool_version::{ResolveOptions, ToolVersion};
 pub use tool_version_list::ToolVersionList;
+use versions::Versioning;
 
 mod builder;
+mod install_reporter;
 pub(crate) mod install_state;
 pub(crate) mod outdated_info;
 pub(crate) mod tool_request;
 mod tool_request_set;
 mod tool_source;
+mod tool_spec;
 mod tool_version;
 mod tool_version_list;
 
@@ -50,6 +66,15 @@ pub struct ToolVersionOptions {
     pub opts: BTreeMap<String, String>,
 }
 
+/// what ToolVersionOptions::merge_with does with a key that is already set
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    #[default]
+    KeepExisting,
+    /// the merged-in value replaces the existing one, e.g. so CLI options win over mise.toml
+    Overwrite,
+}
+
 impl ToolVersionOptions {
     pub fn is_empty(&self) -> bool {
         self.install_env.is_empty() && self.opts.is_empty()
@@ -60,10 +85,21 @@ impl ToolVersionOptions {
     }
 
     pub fn merge(&mut self, other: &BTreeMap<String, String>) {
+        self.merge_with(other, MergeStrategy::KeepExisting);
+    }
+
+    pub fn merge_with(&mut self, other: &BTreeMap<String, String>, strategy: MergeStrategy) {
         for (key, value) in other {
-            self.opts
-                .entry(key.to_string())
-                .or_insert(value.to_string());
+            match strategy {
+                MergeStrategy::KeepExisting => {
+                    self.opts
+                        .entry(key.to_string())
+                        .or_insert(value.to_string());
+                }
+                MergeStrategy::Overwrite => {
+                    self.opts.insert(key.to_string(), value.to_string());
+                }
+            }
         }
     }
 
@@ -72,18 +108,92 @@ impl ToolVersionOptions {
     }
 }
 
+/// parses `k=v,k2=v2` tool options. a value can be wrapped in double quotes to keep commas in it,
+/// and `\,`, `\=`, and `\"` escape a single character anywhere. escapes take precedence over
+/// quoting: `\"` is a literal quote that never opens or closes a quoted value, and the escapes
+/// are only removed after the quotes around a value are stripped.
 pub fn parse_tool_options(s: &str) -> ToolVersionOptions {
     let mut tvo = ToolVersionOptions::default();
-    for opt in s.split(',') {
-        let (k, v) = opt.split_once('=').unwrap_or((opt, ""));
+    for opt in split_tool_options(s) {
+        let (k, v) = match find_unescaped(opt, '=') {
+            Some(i) => (&opt[..i], &opt[i + 1..]),
+            None => (opt, ""),
+        };
         if k.is_empty() {
             continue;
         }
-        tvo.opts.insert(k.to_string(), v.to_string());
+        let v = v
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(v);
+        tvo.opts
+            .insert(unescape_tool_option(k), unescape_tool_option(v));
     }
     tvo
 }
 
+/// splits on unescaped commas outside of double quotes, or on every unescaped comma if the quotes
+/// are unbalanced
+fn split_tool_options(s: &str) -> Vec<&str> {
+    let mut opts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|(_, c)| is_tool_option_escape(*c)) => {
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                opts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        let mut opts = vec![];
+        let mut rest = s;
+        while let Some(i) = find_unescaped(rest, ',') {
+            opts.push(&rest[..i]);
+            rest = &rest[i + 1..];
+        }
+        opts.push(rest);
+        return opts;
+    }
+    opts.push(&s[start..]);
+    opts
+}
+
+fn find_unescaped(s: &str, needle: char) -> Option<usize> {
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(|(_, c)| is_tool_option_escape(*c)) {
+            chars.next();
+        } else if c == needle {
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn unescape_tool_option(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && is_tool_option_escape(*next) => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn is_tool_option_escape(c: char) -> bool {
+    matches!(c, ',' | '=' | '"')
+}
+
 #[derive(Debug, Clone)]
 pub struct InstallOptions {
     pub force: bool,
@@ -93,6 +203,28 @@ pub struct InstallOptions {
     pub missing_args_only: bool,
     pub auto_install_disable_tools: Option<Vec<String>>,
     pub resolve_options: ResolveOptions,
+    /// how many more times to try a tool install which failed with a retriable error
+    pub retries: usize,
+    /// wait before the first retry, doubled for each one after
+    pub retry_delay: Duration,
+    /// how long each tool version may take to install, including retries
+    pub timeout: Option<Duration>,
+    /// progress report for this batch, defaults to the global one
+    pub mpr: Option<Arc<MultiProgressReport>>,
+    /// limits concurrent installs, defaults to a new one with `jobs` permits
+    pub semaphore: Option<Arc<Semaphore>>,
+    /// resolve and report what would be installed without installing anything
+    pub dry_run: bool,
+    /// when an install fails, remove the tool versions the batch had already installed
+    pub rollback_on_failure: bool,
+    /// keep each tool's install output in its [InstallRecord]
+    pub capture_logs: bool,
+    /// also report start, progress, success and failure of each install here
+    pub reporter: InstallReporter,
+    /// how long each install took and what it printed
+    pub records: InstallRecords,
+    /// only install tools from these backend types, e.g. `core` or `aqua`
+    pub allowed_backends: Option<Vec<String>>,
 }
 
 impl Default for InstallOptions {
@@ -104,7 +236,35 @@ impl Default for InstallOptions {
             missing_args_only: true,
             auto_install_disable_tools: SETTINGS.auto_install_disable_tools.clone(),
             resolve_options: Default::default(),
+            retries: SETTINGS.install_retries,
+            retry_delay: SETTINGS.install_retry_delay(),
+            timeout: SETTINGS.install_timeout(),
+            mpr: None,
+            semaphore: None,
+            dry_run: false,
+            rollback_on_failure: false,
+            capture_logs: false,
+            reporter: Default::default(),
+            records: Default::default(),
+            allowed_backends: None,
+        }
+    }
+}
+
+impl InstallOptions {
+    pub fn mpr(&self) -> Arc<MultiProgressReport> {
+        self.mpr.clone().unwrap_or_else(MultiProgressReport::get)
+    }
+
+    pub fn semaphore(&self) -> Arc<Semaphore> {
+        if let Some(semaphore) = &self.semaphore {
+            return semaphore.clone();
         }
+        let jobs = match self.raw || SETTINGS.raw {
+            true => 1,
+            false => self.jobs.unwrap_or(SETTINGS.jobs),
+        };
+        Arc::new(Semaphore::new(jobs))
     }
 }
 
@@ -113,11 +273,86 @@ impl Default for InstallOptions {
 /// one example is a .tool-versions file
 /// the idea is that we start with an empty toolset, then
 /// merge in other toolsets from various sources
-#[derive(Debug, Default, Clone)]
+#[derive(Default, Clone)]
 pub struct Toolset {
     pub versions: IndexMap<Arc<BackendArg>, ToolVersionList>,
     pub source: Option<ToolSource>,
     tera_ctx: OnceCell<tera::Context>,
+    installed: InstalledCache,
+    tool_env: ToolEnvCache,
+    /// how many times the uv venv was looked up, env_with_path does it once
+    #[cfg(test)]
+    venv_lookups: Arc<AtomicUsize>,
+    /// how many times the final PATH dirs were listed, env_no_path never does it
+    #[cfg(test)]
+    final_path_lookups: Arc<AtomicUsize>,
+}
+
+// the caches are left out, they're the same for every toolset until something is looked up
+impl std::fmt::Debug for Toolset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Toolset")
+            .field("versions", &self.versions)
+            .field("source", &self.source)
+            .field("tera_ctx", &self.tera_ctx)
+            .finish()
+    }
+}
+
+/// memoizes is_version_installed so env, list_paths, and which don't stat the same install dirs
+/// over and over. it is shared between clones of a toolset and cleared whenever it installs or
+/// something is uninstalled.
+#[derive(Debug, Default, Clone)]
+struct InstalledCache {
+    installed: Arc<DashMap<ToolVersion, bool>>,
+    #[cfg(test)]
+    misses: Arc<AtomicUsize>,
+    /// the installed version providing each bin name looked up with which, or None if nothing does
+    which: Arc<DashMap<String, Option<ToolVersion>>>,
+    #[cfg(test)]
+    which_misses: Arc<AtomicUsize>,
+}
+
+impl InstalledCache {
+    fn is_installed(&self, config: &Config, p: &dyn Backend, tv: &ToolVersion) -> bool {
+        if let Some(installed) = self.installed.get(tv) {
+            return *installed;
+        }
+        #[cfg(test)]
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let installed = p.is_version_installed(config, tv, true);
+        self.installed.insert(tv.clone(), installed);
+        installed
+    }
+
+    fn clear(&self) {
+        self.installed.clear();
+        self.which.clear();
+    }
+}
+
+/// an env value meaning "remove this var from the inherited env" rather than setting it, for
+/// configs that only have string values to work with
+pub const ENV_UNSET: &str = "!unset";
+
+type ToolEnv = Vec<(String, String, String)>;
+/// the installed versions an env was computed from with their options, which ToolVersion's Eq and
+/// Hash leave out but exec-env can depend on, and whether it was strict
+type ToolEnvKey = (Vec<(ToolVersion, ToolVersionOptions)>, bool);
+
+/// memoizes env_from_tools for the installed versions it was computed from so env, final_env, and
+/// hook-env don't run every exec-env again. shared between clones and cleared on install.
+#[derive(Debug, Default, Clone)]
+struct ToolEnvCache {
+    envs: Arc<DashMap<ToolEnvKey, Arc<OnceCell<ToolEnv>>>>,
+    #[cfg(test)]
+    misses: Arc<AtomicUsize>,
+}
+
+impl ToolEnvCache {
+    fn clear(&self) {
+        self.envs.clear();
+    }
 }
 
 impl Toolset {
@@ -138,6 +373,14 @@ impl Toolset {
             .or_insert_with(|| ToolVersionList::new(ba.clone(), self.source.clone().unwrap()));
         tvl.requests.push(tvr);
     }
+    /// the inverse of add_version, returning the removed requests and versions. like add_version
+    /// it ignores disabled tools, so removing one is a no-op returning None.
+    pub fn remove_version(&mut self, ba: &BackendArg) -> Option<ToolVersionList> {
+        if self.is_disabled(ba) {
+            return None;
+        }
+        self.versions.shift_remove(ba)
+    }
     pub fn merge(&mut self, other: Toolset) {
         let mut versions = other.versions;
         for (plugin, tvl) in self.versions.clone() {
@@ -149,11 +392,60 @@ impl Toolset {
         self.versions = versions;
         self.source = other.source;
     }
-    pub async fn resolve(&mut self) -> eyre::Result<()> {
+    /// what would change going from this toolset to `other`, comparing resolved versions
+    pub fn diff(&self, other: &Toolset) -> ToolsetDiff {
+        let resolved = |tvl: &ToolVersionList| {
+            tvl.versions
+                .iter()
+                .map(|tv| tv.version.clone())
+                .collect_vec()
+        };
+        let mut diff = ToolsetDiff::default();
+        for (ba, tvl) in &self.versions {
+            match other.versions.get(ba) {
+                None => diff.removed.push(ba.clone()),
+                Some(other_tvl) => {
+                    let (old, new) = (resolved(tvl), resolved(other_tvl));
+                    if old != new {
+                        diff.changed.push((ba.clone(), old, new));
+                    }
+                }
+            }
+        }
+        diff.added = other
+            .versions
+            .keys()
+            .filter(|ba| !self.versions.contains_key(*ba))
+            .cloned()
+            .collect();
+        diff
+    }
+    /// removes any tools which are disabled by the current settings, returning their short names
+    /// add_version/merge already skip disabled tools but a toolset may have been built before
+    /// the settings changed
+    pub fn clear_disabled(&mut self) -> Vec<String> {
+        let disabled = self
+            .versions
+            .keys()
+            .filter(|ba| self.is_disabled(ba))
+            .cloned()
+            .collect_vec();
+        for ba in &disabled {
+            self.versions.shift_remove(ba);
+        }
+        disabled.into_iter().map(|ba| ba.short.clone()).collect()
+    }
+    /// resolves the requests of every tool. with `reuse_resolved`, tools whose requests are exact
+    /// versions that are already resolved are left alone so only new or changed tools resolve.
+    pub async fn resolve(&mut self, reuse_resolved: bool) -> eyre::Result<()> {
         let config = Config::get().await;
-        self.list_missing_plugins();
+        self.list_missing_plugins().await;
         let mut jset: JoinSet<Result<_>> = JoinSet::new();
         for (i, (ba, mut tvl)) in self.versions.clone().into_iter().enumerate() {
+            if reuse_resolved && tvl.is_concretely_resolved() {
+                trace!("reusing resolved versions of {ba}");
+                continue;
+            }
             let config = config.clone();
             jset.spawn(async move {
                 tvl.resolve(&config, &Default::default()).await?;
@@ -174,6 +466,46 @@ impl Toolset {
         }
         Ok(())
     }
+    /// like resolve but gives up on tools which are still resolving at `deadline`, leaving them
+    /// unresolved. Returns the tools that did not finish in time.
+    pub async fn resolve_with_deadline(
+        &mut self,
+        config: &Arc<Config>,
+        deadline: Instant,
+    ) -> Result<Vec<Arc<BackendArg>>> {
+        self.list_missing_plugins().await;
+        let mut jset: JoinSet<Result<_>> = JoinSet::new();
+        for (ba, mut tvl) in self.versions.clone() {
+            let config = config.clone();
+            jset.spawn(async move {
+                tvl.resolve(&config, &Default::default()).await?;
+                Ok((ba, tvl))
+            });
+        }
+        let mut unresolved = self.versions.keys().cloned().collect::<IndexSet<_>>();
+        for (ba, tvl) in join_until(jset, deadline).await? {
+            unresolved.shift_remove(&ba);
+            self.versions.insert(ba, tvl);
+        }
+        let unresolved = unresolved.into_iter().collect_vec();
+        if !unresolved.is_empty() {
+            warn!(
+                "timed out resolving versions for {}",
+                unresolved.iter().map(|ba| ba.short.as_str()).join(", ")
+            );
+        }
+        Ok(unresolved)
+    }
+    /// the receipt written when `version` of `ba` was installed
+    pub fn read_receipt(&self, ba: &BackendArg, version: &str) -> Result<Option<sbom::Receipt>> {
+        let install_path = self
+            .versions
+            .get(ba)
+            .and_then(|tvl| tvl.versions.iter().find(|tv| tv.version == version))
+            .map(|tv| tv.install_path())
+            .unwrap_or_else(|| ba.installs_path.join(version.replace([':', '/'], "-")));
+        sbom::read_receipt(&install_path)
+    }
     pub async fn install_missing_versions(
         &mut self,
         config: &Arc<Config>,
@@ -197,14 +529,18 @@ impl Toolset {
             .map(|tv| tv.request)
             .collect_vec();
         let versions = self.install_all_versions(config, versions, opts).await?;
-        if !versions.is_empty() {
+        if !versions.is_empty() && !opts.dry_run {
             config::rebuild_shims_and_runtime_symlinks(&versions).await?;
         }
         Ok(versions)
     }
 
-    pub fn list_missing_plugins(&self) -> Vec<String> {
-        self.versions
+    /// the plugins of current tools which are not installed. each check stats the plugin dir so
+    /// they run concurrently.
+    pub async fn list_missing_plugins(&self) -> Vec<String> {
+        let mut jset = JoinSet::new();
+        for (i, b) in self
+            .versions
             .iter()
             .filter(|(_, tvl)| {
                 tvl.versions
@@ -214,20 +550,31 @@ impl Toolset {
             })
             .map(|(ba, _)| ba)
             .flat_map(|ba| ba.backend())
-            .filter(|b| b.plugin().is_some_and(|p| !p.is_installed()))
-            .map(|p| p.id().into())
+            .enumerate()
+        {
+            jset.spawn_blocking(move || {
+                b.plugin()
+                    .is_some_and(|p| !p.is_installed())
+                    .then(|| (i, b.id().to_string()))
+            });
+        }
+        jset.join_all()
+            .await
+            .into_iter()
+            .flatten()
+            .sorted_by_key(|(i, _)| *i)
+            .map(|(_, id)| id)
             .collect()
     }
 
     /// sets the options on incoming requests to install to whatever is already in the toolset
     /// this handles the use-case where you run `mise use ubi:cilium/cilium-cli` (without CLi options)
-    /// but this tool has options inside mise.toml
+    /// but this tool has options inside mise.toml. the backend's default options are filled in
+    /// beneath whatever the request ends up with.
     fn init_request_options(&self, requests: &mut Vec<ToolRequest>) {
-        for tr in requests {
-            // TODO: tr.options() probably should be Option<ToolVersionOptions>
-            // to differentiate between no options and empty options
-            // without that it might not be possible to unset the options if they are set
-            if !tr.options().is_empty() {
+        for tr in requests.iter_mut() {
+            // options set explicitly, even to nothing with `tool[]`, are not backfilled
+            if tr.explicit_options().is_some() {
                 continue;
             }
             if let Some(tvl) = self.versions.get(tr.ba()) {
@@ -239,6 +586,11 @@ impl Toolset {
                 tr.set_options(options);
             }
         }
+        for tr in requests.iter_mut() {
+            if let Ok(backend) = tr.backend() {
+                apply_default_options(tr, &*backend);
+            }
+        }
     }
 
     pub async fn install_all_versions(
@@ -250,23 +602,52 @@ impl Toolset {
         if versions.is_empty() {
             return Ok(vec![]);
         }
-        hooks::run_one_hook(self, Hooks::Preinstall, None).await;
+        ensure_known_tools(&versions)?;
+        if let Some(allowed) = &opts.allowed_backends {
+            ensure_allowed_backends(&versions, allowed)?;
+        }
+        if !opts.dry_run {
+            hooks::run_one_hook(self, Hooks::Preinstall, None).await;
+        }
         self.init_request_options(&mut versions);
         show_python_install_hint(&versions);
+        // one semaphore for every batch so the jobs limit holds across them
+        let opts = &InstallOptions {
+            semaphore: Some(opts.semaphore()),
+            ..opts.clone()
+        };
         let mut installed = vec![];
+        // versions that were not installed before this call, the ones a rollback removes
+        let fresh = Arc::new(Mutex::new(vec![]));
         let mut leaf_deps = get_leaf_dependencies(&versions)?;
         while !leaf_deps.is_empty() {
             if leaf_deps.len() < versions.len() {
                 debug!("installing {} leaf tools first", leaf_deps.len());
             }
             versions.retain(|tr| !leaf_deps.contains(tr));
-            installed.extend(self.install_some_versions(config, leaf_deps, opts).await?);
+            match self
+                .install_some_versions(config, leaf_deps, opts, &fresh)
+                .await
+            {
+                Ok(tvs) => installed.extend(tvs),
+                Err(err) => {
+                    if opts.rollback_on_failure {
+                        rollback_installs(&fresh.lock().unwrap());
+                    }
+                    // earlier batches may have installed bins before this one failed
+                    self.installed.clear();
+                    return Err(err);
+                }
+            }
             leaf_deps = get_leaf_dependencies(&versions)?;
         }
+        if opts.dry_run {
+            return Ok(installed);
+        }
 
         trace!("install: resolving");
         install_state::reset();
-        if let Err(err) = self.resolve().await {
+        if let Err(err) = self.resolve(true).await {
             debug!("error resolving versions after install: {err:#}");
         }
         if log::log_enabled!(log::Level::Debug) {
@@ -302,19 +683,20 @@ impl Toolset {
         config: &Arc<Config>,
         versions: Vec<ToolRequest>,
         opts: &InstallOptions,
+        fresh: &Arc<Mutex<Vec<ToolVersion>>>,
     ) -> Result<Vec<ToolVersion>> {
         debug!("install_some_versions: {}", versions.iter().join(" "));
-        let queue: Vec<_> = versions
-            .into_iter()
-            .rev()
-            .chunk_by(|v| v.ba().clone())
+        let queue: Vec<_> = install_queue(versions)
             .into_iter()
-            .map(|(ba, v)| Ok((ba.backend()?, v.collect_vec())))
+            .map(|(ba, v)| Ok((ba.backend()?, v)))
             .collect::<Result<_>>()?;
+        let mpr = opts.mpr();
+        if opts.dry_run {
+            return plan_install(config, queue, opts, &mpr).await;
+        }
         for (backend, _) in &queue {
             if let Some(plugin) = backend.plugin() {
                 if !plugin.is_installed() {
-                    let mpr = MultiProgressReport::get();
                     plugin.ensure_installed(&mpr, false).await.or_else(|err| {
                         if let Some(&Error::PluginNotInstalled(_)) = err.downcast_ref::<Error>() {
                             Ok(())
@@ -325,50 +707,154 @@ impl Toolset {
                 }
             }
         }
-        let raw = opts.raw || SETTINGS.raw;
-        let jobs = match raw {
-            true => 1,
-            false => opts.jobs.unwrap_or(SETTINGS.jobs),
-        };
-        let semaphore = Arc::new(Semaphore::new(jobs));
+        let semaphore = opts.semaphore();
+        let backend_semaphores = backend_semaphores(
+            queue
+                .iter()
+                .map(|(ba, _)| ba.ba().backend_type().to_string()),
+            &SETTINGS.jobs_per_backend(),
+        );
         let ts = Arc::new(self.clone());
-        let mut tset: JoinSet<Result<Vec<ToolVersion>, eyre::Report>> = JoinSet::new();
+        let mut tset: JoinSet<Result<(usize, Vec<ToolVersion>), eyre::Report>> = JoinSet::new();
         let opts = Arc::new(opts.clone());
-        for (ba, trs) in queue {
+        for (i, (ba, trs)) in queue.into_iter().enumerate() {
             let ts = ts.clone();
             let semaphore = semaphore.clone();
+            let backend_semaphore = backend_semaphores
+                .get(ba.ba().backend_type().as_ref())
+                .cloned();
             let opts = opts.clone();
             let ba = ba.clone();
             let config = config.clone();
+            let mpr = mpr.clone();
+            let fresh = fresh.clone();
             tset.spawn(async move {
+                // the backend permit is taken first so a capped backend waiting its turn doesn't
+                // hold a global slot other backends could use
+                let _backend_permit = match &backend_semaphore {
+                    Some(s) => Some(s.acquire().await?),
+                    None => None,
+                };
                 let _permit = semaphore.acquire().await?;
-                let mpr = MultiProgressReport::get();
                 let mut installed = vec![];
                 for tr in trs {
                     let tv = tr.resolve(&config, &opts.resolve_options).await?;
-                    let ctx = InstallContext {
-                        ts: ts.clone(),
-                        pr: mpr.add(&tv.style()),
-                        force: opts.force,
-                    };
-                    let old_tv = tv.clone();
-                    let tv = ba
-                        .install_version(ctx, tv)
+                    let existed = ba.is_version_installed(&config, &tv, true);
+                    // a version already installed is left alone unless it is forced
+                    let installing = opts.force || !existed;
+                    if installing {
+                        ensure_disk_space(&*ba, &tv, file::available_space).await?;
+                    }
+                    let start = Instant::now();
+                    opts.reporter.start(&tv, start);
+                    let log = Arc::new(Mutex::new(vec![]));
+                    let recorded = Arc::new(Mutex::new(None));
+                    let retry = (opts.retries, opts.retry_delay);
+                    let install = with_install_retries(&*ba, retry, |attempt| {
+                        let mut pr = opts.reporter.report(&tv, start, mpr.add(&tv.style()));
+                        if opts.capture_logs {
+                            pr = Box::new(CaptureReport::new(pr, log.clone()));
+                        }
+                        let ctx = InstallContext {
+                            ts: ts.clone(),
+                            pr,
+                            force: opts.force,
+                            sbom: recorded.clone(),
+                        };
+                        if attempt > 0 {
+                            ctx.pr
+                                .set_message(format!("retry {attempt}/{}", opts.retries));
+                        }
+                        ba.install_version(ctx, tv.clone())
+                    });
+                    let tv = with_install_timeout(&*ba, &tv, opts.timeout, install)
                         .await
-                        .wrap_err_with(|| format!("failed to install {old_tv}"))?;
+                        .map_err(|err| {
+                            opts.reporter.failure(&tv, start, &err);
+                            err.wrap_err(Error::InstallFailed {
+                                tv: Box::new(tv.clone()),
+                                log: log.lock().unwrap().clone(),
+                            })
+                        })?;
+                    debug!("installed {tv} in {:?}", start.elapsed());
+                    let pr = mpr.add(&tv.style());
+                    if installing {
+                        if let Err(err) = verify_install(&config, &ts, &*ba, &tv, &pr).await {
+                            opts.reporter.failure(&tv, start, &err);
+                            pr.finish_with_message("verification failed".into());
+                            ba.cleanup_install_dirs_on_error(&tv);
+                            // a kept install must not look complete
+                            file::create(&ba.incomplete_file_path(&tv))?;
+                            return Err(err);
+                        }
+                    }
+                    pr.finish();
+                    opts.records.insert(
+                        &tv,
+                        InstallRecord {
+                            duration: start.elapsed(),
+                            log: log.lock().unwrap().clone(),
+                        },
+                    );
+                    opts.reporter.success(&tv, start);
+                    if !existed {
+                        fresh.lock().unwrap().push(tv.clone());
+                    }
+                    // nothing is recorded when no install ran, so the receipt and SBOM entry of
+                    // the real one stay
+                    if installing {
+                        let recorded = recorded.lock().unwrap().take();
+                        let entry = sbom::SbomEntry::from_install(&tv, recorded);
+                        if let Err(err) = sbom::write_receipt(&tv, &entry) {
+                            warn!("failed to write install receipt for {tv}: {err:#}");
+                        }
+                        if let Some(sbom_file) = &SETTINGS.sbom_file {
+                            sbom::record(sbom_file, &entry)
+                                .wrap_err_with(|| format!("failed to write SBOM entry for {tv}"))?;
+                        }
+                    }
                     installed.push(tv);
                 }
-                Ok(installed)
+                Ok((i, installed))
             });
         }
         let mut installed = vec![];
         while let Some(res) = tset.join_next().await {
-            installed.extend(res??);
+            match res? {
+                Ok(tvs) => installed.push(tvs),
+                Err(err) => {
+                    // waits for the other installs to stop so none finish after a rollback
+                    tset.shutdown().await;
+                    return Err(err);
+                }
+            }
         }
-        installed.reverse();
-        Ok(installed)
+        self.installed.clear();
+        self.tool_env.clear();
+        // in install_queue order rather than the order the installs finished in
+        Ok(installed
+            .into_iter()
+            .sorted_by_key(|(i, _)| *i)
+            .flat_map(|(_, tvs)| tvs)
+            .collect())
     }
 
+    /// remote versions of `short` which must be a tool in this toolset. prereleases are
+    /// excluded unless the tool was requested with `prerelease = true`.
+    pub async fn list_remote_versions(&self, short: &str) -> Result<Vec<String>> {
+        let Some((ba, tvl)) = self.versions.iter().find(|(ba, _)| ba.short == short) else {
+            bail!("{short} is not in the current toolset");
+        };
+        let prerelease = tvl
+            .requests
+            .iter()
+            .any(|tr| tr.options().get("prerelease").is_some_and(|v| v == "true"));
+        let versions = ba.backend()?.list_remote_versions().await?;
+        Ok(versions
+            .into_iter()
+            .filter(|v| prerelease || !VERSION_REGEX.is_match(v))
+            .collect())
+    }
     pub async fn list_missing_versions(&self) -> Vec<ToolVersion> {
         let config = Config::get().await;
         measure!("toolset::list_missing_versions", {
@@ -389,11 +875,11 @@ impl Toolset {
             .map(|(p, tv)| ((p.id().into(), tv.version.clone()), (p.clone(), tv)))
             .collect();
         let current_versions = Arc::new(current_versions);
-        let mut jset: JoinSet<Result<_>> = JoinSet::new();
-        for (i, b) in backend::list().into_iter().enumerate() {
+        // each backend reads its installs dir so this is bounded to avoid an I/O storm
+        let versions = join_bounded(backend::list(), Settings::get().jobs, move |b| {
             let current_versions = current_versions.clone();
             let config = config.clone();
-            jset.spawn(async move {
+            async move {
                 let mut versions = vec![];
                 for v in b.list_installed_versions()? {
                     if let Some((p, tv)) = current_versions.get(&(b.id().into(), v.clone())) {
@@ -404,19 +890,57 @@ impl Toolset {
                         .await?;
                     versions.push((b.clone(), tv));
                 }
-                Ok((i, versions))
-            });
-        }
-        let versions = jset
-            .join_all()
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>>>()?
+                Ok(versions)
+            }
+        })
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+        Ok(versions)
+    }
+    /// installed versions that nothing in this toolset pins, e.g. for cleaning up old installs.
+    /// runtime symlinks like `20 -> ./20.1.0` are not orphans, see [Self::orphaned_symlinks].
+    pub fn orphaned_versions(&self) -> Result<Vec<TVTuple>> {
+        Ok(self.list_orphans()?.0)
+    }
+    /// runtime symlinks pointing at orphaned versions, they dangle once those are removed
+    pub fn orphaned_symlinks(&self) -> Result<Vec<TVTuple>> {
+        Ok(self.list_orphans()?.1)
+    }
+    fn list_orphans(&self) -> Result<(Vec<TVTuple>, Vec<TVTuple>)> {
+        let current: HashSet<(String, String)> = self
+            .list_current_versions()
             .into_iter()
-            .sorted_by_key(|(i, _)| *i)
-            .flat_map(|(_, versions)| versions)
+            .map(|(p, tv)| (p.id().to_string(), tv.version))
             .collect();
-        Ok(versions)
+        let mut orphans = vec![];
+        let mut symlinks = vec![];
+        for b in backend::list() {
+            let tv = |v: &str| -> Result<ToolVersion> {
+                let tr = ToolRequest::new(b.ba().clone(), v, ToolSource::Unknown)?;
+                Ok(ToolVersion::new(tr, v.to_string()))
+            };
+            let is_orphan = |v: &str| !current.contains(&(b.id().to_string(), v.to_string()));
+            // installed versions never include runtime symlinks
+            for v in b.list_installed_versions()? {
+                if is_orphan(&v) {
+                    orphans.push((b.clone(), tv(&v)?));
+                }
+            }
+            for path in file::ls(&b.ba().installs_path).unwrap_or_default() {
+                if !is_runtime_symlink(&path) {
+                    continue;
+                }
+                let target = file::resolve_symlink(&path)?;
+                let target = target.to_string_lossy();
+                if is_orphan(target.trim_start_matches("./")) {
+                    let name = path.file_name().unwrap().to_string_lossy();
+                    symlinks.push((b.clone(), tv(&name)?));
+                }
+            }
+        }
+        Ok((orphans, symlinks))
     }
     pub fn list_current_requests(&self) -> Vec<&ToolRequest> {
         self.versions
@@ -424,6 +948,17 @@ impl Toolset {
             .flat_map(|tvl| &tvl.requests)
             .collect()
     }
+    /// list_current_requests limited to those whose source matches `source_pred`, e.g. only the
+    /// ones pinned by a project rather than inherited from a parent config
+    pub fn list_own_requests<F>(&self, source_pred: F) -> Vec<&ToolRequest>
+    where
+        F: Fn(&ToolSource) -> bool,
+    {
+        self.list_current_requests()
+            .into_iter()
+            .filter(|tr| source_pred(tr.source()))
+            .collect()
+    }
     pub fn list_versions_by_plugin(&self) -> Vec<(Arc<dyn Backend>, &Vec<ToolVersion>)> {
         self.versions
             .iter()
@@ -437,12 +972,17 @@ impl Toolset {
                 v.iter().map(|v| {
                     // map cargo backend specific prefixes to ref
                     let tv = match v.version.split_once(':') {
-                        Some((ref_type @ ("tag" | "branch" | "rev"), r)) => {
+                        Some((ref_type, r))
+                            if matches!(
+                                tool_request::parse_ref_type(ref_type),
+                                Some("tag" | "branch" | "rev")
+                            ) =>
+                        {
                             let request = ToolRequest::Ref {
                                 backend: p.ba().clone(),
                                 ref_: r.to_string(),
-                                ref_type: ref_type.to_string(),
-                                options: v.request.options().clone(),
+                                ref_type: ref_type.to_lowercase(),
+                                options: v.request.explicit_options().cloned(),
                                 source: v.request.source().clone(),
                             };
                             let version = format!("ref:{r}");
@@ -455,6 +995,27 @@ impl Toolset {
             })
             .collect()
     }
+    /// like list_current_versions but also includes requests which have not been resolved yet,
+    /// the bool is false for those so UIs can show them as pending
+    pub fn list_current_versions_with_state(&self) -> Vec<(Arc<dyn Backend>, ToolVersion, bool)> {
+        let mut versions = vec![];
+        for (ba, tvl) in &self.versions {
+            let Ok(backend) = ba.backend() else {
+                continue;
+            };
+            if tvl.versions.is_empty() {
+                for tr in &tvl.requests {
+                    let tv = ToolVersion::new(tr.clone(), tr.version());
+                    versions.push((backend.clone(), tv, false));
+                }
+            } else {
+                for tv in &tvl.versions {
+                    versions.push((backend.clone(), tv.clone(), tv.is_resolved()));
+                }
+            }
+        }
+        versions
+    }
     pub async fn list_all_versions(&self) -> Result<Vec<(Arc<dyn Backend>, ToolVersion)>> {
         let versions = self
             .list_current_versions()
@@ -470,44 +1031,76 @@ impl Toolset {
     ) -> Vec<(Arc<dyn Backend>, ToolVersion)> {
         self.list_current_versions()
             .into_iter()
-            .filter(|(p, v)| p.is_version_installed(config, v, true))
+            .filter(|(p, v)| self.installed.is_installed(config, p.as_ref(), v))
             .collect()
     }
     pub async fn list_outdated_versions(&self, bump: bool) -> Vec<OutdatedInfo> {
         let config = Config::get().await;
-        let mut jset = JoinSet::new();
-        for (i, (t, tv)) in self.list_current_versions().into_iter().enumerate() {
-            let config = config.clone();
-            jset.spawn(async move {
-                match t.outdated_info(&tv, bump).await {
-                    Ok(Some(oi)) => return Some((i, oi)),
-                    Ok(None) => {}
-                    Err(e) => {
-                        warn!("Error getting outdated info for {tv}: {e:#}");
-                        return None;
+        // each check can hit a remote registry so this is bounded to avoid rate limits
+        let outdated = join_bounded(
+            self.list_current_versions(),
+            Settings::get().jobs,
+            move |(t, tv)| {
+                let config = config.clone();
+                async move {
+                    match t.outdated_info(&tv, bump).await {
+                        Ok(Some(oi)) => return Ok(Some(oi)),
+                        Ok(None) => {}
+                        Err(e) => {
+                            warn!("Error getting outdated info for {tv}: {e:#}");
+                            return Ok(None);
+                        }
                     }
+                    if t.symlink_path(&tv).is_some() {
+                        trace!("skipping symlinked version {tv}");
+                        // do not consider symlinked versions to be outdated
+                        return Ok(None);
+                    }
+                    Ok(OutdatedInfo::resolve(&config, tv.clone(), bump)
+                        .await
+                        .unwrap_or_else(|e| {
+                            warn!("Error creating OutdatedInfo for {tv}: {e:#}");
+                            None
+                        }))
                 }
-                if t.symlink_path(&tv).is_some() {
-                    trace!("skipping symlinked version {tv}");
-                    // do not consider symlinked versions to be outdated
-                    return None;
-                }
-                OutdatedInfo::resolve(&config, tv.clone(), bump)
-                    .await
-                    .unwrap_or_else(|e| {
-                        warn!("Error creating OutdatedInfo for {tv}: {e:#}");
-                        None
-                    })
-                    .map(|oi| (i, oi))
-            });
+            },
+        )
+        .await;
+        match outdated {
+            Ok(outdated) => outdated.into_iter().flatten().collect(),
+            Err(e) => {
+                warn!("Error checking for outdated versions: {e:#}");
+                vec![]
+            }
         }
-        jset.join_all()
+    }
+    /// installs the latest version of every outdated tool and rebuilds shims. symlinked versions
+    /// are never considered outdated so they are left alone.
+    pub async fn upgrade_outdated(
+        &mut self,
+        config: &Arc<Config>,
+        bump: bool,
+        opts: &InstallOptions,
+    ) -> Result<Vec<ToolVersion>> {
+        let versions = self
+            .list_outdated_versions(bump)
             .await
             .into_iter()
-            .flatten()
-            .sorted_by_key(|(i, _)| *i)
-            .map(|(_, oi)| oi)
-            .collect()
+            .map(|oi| ToolRequest::Version {
+                backend: oi.tool_request.ba().clone(),
+                version: oi.latest,
+                options: oi.tool_request.explicit_options().cloned(),
+                source: oi.tool_request.source().clone(),
+            })
+            .collect_vec();
+        if versions.is_empty() {
+            return Ok(vec![]);
+        }
+        let installed = self.install_all_versions(config, versions, opts).await?;
+        shims::reshim(self, false)
+            .await
+            .wrap_err("failed to rebuild shims")?;
+        Ok(installed)
     }
     /// returns env_with_path but also with the existing env vars from the system
     pub async fn full_env(&self, config: &Config) -> Result<EnvMap> {
@@ -517,22 +1110,99 @@ impl Toolset {
     }
     /// the full mise environment including all tool paths
     pub async fn env_with_path(&self, config: &Config) -> Result<EnvMap> {
-        let (mut env, env_results) = self.final_env(config).await?;
+        let venv = self.uv_venv().await;
+        let (mut env, env_results) = self
+            .final_env_with_venv(config, venv.as_ref(), &env::PRISTINE_ENV)
+            .await?;
         let mut path_env = PathEnv::from_iter(env::PATH.clone());
-        for p in self.list_final_paths(config, env_results).await? {
+        for p in self
+            .list_final_paths_with_venv(config, env_results, venv.as_ref())
+            .await?
+        {
             path_env.add(p.clone());
         }
         env.insert(PATH_KEY.to_string(), path_env.to_string());
         Ok(env)
     }
+    /// env_with_path without assembling PATH, for callers that only need the other vars
+    pub async fn env_no_path(&self, config: &Config) -> Result<EnvMap> {
+        let (mut env, _) = self.final_env(config).await?;
+        env.remove(&*PATH_KEY);
+        Ok(env)
+    }
+    /// env_with_path using only the exec_env and bin paths of the tools in `shorts`, plus the
+    /// config env, e.g. for `mise exec node -- cmd`
+    pub async fn env_with_path_for_tools(
+        &self,
+        config: &Config,
+        shorts: &[String],
+    ) -> Result<EnvMap> {
+        if let Some(short) = shorts
+            .iter()
+            .find(|s| !self.versions.keys().any(|ba| &ba.short == *s))
+        {
+            bail!("{short} is not in the current toolset");
+        }
+        let ts = Toolset {
+            versions: self
+                .versions
+                .iter()
+                .filter(|(ba, _)| shorts.contains(&ba.short))
+                .map(|(ba, tvl)| (ba.clone(), tvl.clone()))
+                .collect(),
+            source: self.source.clone(),
+            ..Default::default()
+        };
+        ts.env_with_path(config).await
+    }
+    /// env_with_path for `dir`, reused across calls in this process until a config file that
+    /// applies to `dir` changes or a tool is installed. `config` should be the config loaded for
+    /// `dir`. the env is only kept in memory since it can hold secrets.
+    pub async fn env_for_dir(&self, config: &Config, dir: &Path) -> Result<EnvMap> {
+        static DIR_ENVS: Lazy<DashMap<PathBuf, (String, EnvMap)>> = Lazy::new(Default::default);
+        let installed = self
+            .list_current_installed_versions(config)
+            .into_iter()
+            .map(|(_, tv)| tv)
+            .collect_vec();
+        let key = dir_env_key(config.config_files.keys(), &installed);
+        if let Some(entry) = DIR_ENVS.get(dir) {
+            if entry.0 == key {
+                return Ok(entry.1.clone());
+            }
+        }
+        let env = self.env_with_path(config).await?;
+        DIR_ENVS.insert(dir.to_path_buf(), (key, env.clone()));
+        Ok(env)
+    }
     pub async fn env_from_tools(&self) -> Vec<(String, String, String)> {
-        let mut jset = JoinSet::new();
+        self.env_from_tools_strict(false).await.unwrap_or_default()
+    }
+    /// env_from_tools but when `strict` is set a failing exec-env is an error instead of a warning
+    pub async fn env_from_tools_strict(
+        &self,
+        strict: bool,
+    ) -> Result<Vec<(String, String, String)>> {
         let config = Config::get().await;
-        for (i, (b, tv)) in self
-            .list_current_installed_versions(&config)
-            .into_iter()
-            .enumerate()
-        {
+        let versions = self.list_current_installed_versions(&config);
+        let key = (
+            versions
+                .iter()
+                .map(|(_, tv)| (tv.clone(), tv.request.options()))
+                .collect(),
+            strict,
+        );
+        let cell = self.tool_env.envs.entry(key).or_default().clone();
+        let env = cell
+            .get_or_try_init(|| self.load_env_from_tools(versions, strict))
+            .await?;
+        Ok(env.clone())
+    }
+    async fn load_env_from_tools(&self, versions: Vec<TVTuple>, strict: bool) -> Result<ToolEnv> {
+        #[cfg(test)]
+        self.tool_env.misses.fetch_add(1, Ordering::Relaxed);
+        let mut jset = JoinSet::new();
+        for (i, (b, tv)) in versions.into_iter().enumerate() {
             if matches!(tv.request, ToolRequest::System { .. }) {
                 continue;
             }
@@ -540,65 +1210,208 @@ impl Toolset {
             jset.spawn(async move {
                 let config = Config::get().await;
                 match b.exec_env(&config, &this, &tv).await {
-                    Ok(env) => env
+                    Ok(env) => Ok(env
                         .into_iter()
                         .map(|(k, v)| (i, k, v, b.id().to_string()))
-                        .collect(),
+                        .collect()),
+                    Err(e) if strict => Err(e.wrap_err(format!("failed to run exec-env for {tv}"))),
                     Err(e) => {
                         warn!("Error running exec-env: {:#}", e);
-                        Vec::new()
+                        Ok(Vec::new())
                     }
                 }
             });
         }
-        jset.join_all()
+        Ok(jset
+            .join_all()
             .await
             .into_iter()
+            .collect::<Result<Vec<Vec<_>>>>()?
+            .into_iter()
             .flatten()
             .sorted_by_key(|(i, _, _, _)| *i)
             .map(|(_, k, v, id)| (k, v, id))
             .filter(|(k, _, _)| k.to_uppercase() != "PATH")
-            .collect()
+            .collect())
     }
-    async fn env(&self, config: &Config) -> Result<EnvMap> {
-        time!("env start");
-        let entries = self
-            .env_from_tools()
+    /// env_from_tools restricted to the keys in `allow`
+    pub async fn env_from_tools_filtered(&self, allow: &[String]) -> Vec<(String, String, String)> {
+        if allow.is_empty() {
+            return vec![];
+        }
+        self.env_from_tools()
             .await
             .into_iter()
-            .map(|(k, v, _)| (k, v))
-            .collect::<Vec<(String, String)>>();
-        let add_paths = entries
-            .iter()
-            .filter(|(k, _)| k == "MISE_ADD_PATH" || k == "RTX_ADD_PATH")
-            .map(|(_, v)| v)
-            .join(":");
-        let mut env: EnvMap = entries
+            .filter(|(k, _, _)| allow.contains(k))
+            .collect()
+    }
+    /// looks up a single var from the final env, only running tools' exec_env until one of them
+    /// sets `key`. PATH, `tools = true` env directives, unset directives, and env_finalize hooks
+    /// depend on everything so they fall back to computing the full env.
+    pub async fn env_var(&self, config: &Config, key: &str) -> Result<Option<String>> {
+        // post env, unset directives, and env_finalize hooks can change any var so only the
+        // final env has the right value
+        let needs_final_env = config::env_config_files(&config.config_files)
             .into_iter()
-            .filter(|(k, _)| k != "RTX_ADD_PATH")
-            .filter(|(k, _)| k != "MISE_ADD_PATH")
-            .filter(|(k, _)| !k.starts_with("RTX_TOOL_OPTS__"))
-            .filter(|(k, _)| !k.starts_with("MISE_TOOL_OPTS__"))
-            .rev()
-            .collect();
-        if !add_paths.is_empty() {
+            .map(|(_, cf)| cf.env_entries())
+            .flatten_ok()
+            .process_results(|mut entries| {
+                entries.any(|e| e.options().tools || matches!(e, EnvDirective::Rm(..)))
+            })?;
+        let has_env_finalize = config
+            .hooks()
+            .await?
+            .iter()
+            .any(|(_, h)| h.hook == Hooks::EnvFinalize);
+        if needs_final_env || has_env_finalize || key == *PATH_KEY {
+            let (env, _) = self.final_env(config).await?;
+            return Ok(env.get(key).cloned());
+        }
+        // the same post-processing final_env does to each var
+        let finish = |v: &String| {
+            let mut env = EnvMap::from([(key.to_string(), v.clone())]);
+            expand_home_in_paths(&mut env);
+            env.remove(key).filter(|v| v != ENV_UNSET)
+        };
+        if let Some(venv) = self.uv_venv().await {
+            if let Some(v) = venv.env.get(key) {
+                return Ok(finish(v));
+            }
+        }
+        if let Some(v) = config.env().await?.get(key) {
+            return Ok(finish(v));
+        }
+        if key.starts_with("RTX_TOOL_OPTS__") || key.starts_with("MISE_TOOL_OPTS__") {
+            return Ok(None);
+        }
+        for (b, tv) in self.list_current_installed_versions(config) {
+            if matches!(tv.request, ToolRequest::System { .. }) {
+                continue;
+            }
+            match b.exec_env(config, self, &tv).await {
+                Ok(env) => {
+                    if let Some(v) = env.get(key) {
+                        return Ok(finish(v));
+                    }
+                }
+                Err(e) => warn!("Error running exec-env: {:#}", e),
+            }
+        }
+        Ok(None)
+    }
+    /// the final env with the config file that set each var. vars from tools are attributed to
+    /// the tool id and vars from the uv venv to the venv path.
+    pub async fn env_with_sources(
+        &self,
+        config: &Config,
+    ) -> Result<Vec<(String, String, PathBuf)>> {
+        let mut env: IndexMap<String, (String, PathBuf)> = IndexMap::new();
+        for (k, v, id) in self.env_from_tools().await.into_iter().rev() {
+            if k == "RTX_ADD_PATH"
+                || k == "MISE_ADD_PATH"
+                || k.starts_with("RTX_TOOL_OPTS__")
+                || k.starts_with("MISE_TOOL_OPTS__")
+            {
+                continue;
+            }
+            env.insert(k, (v, PathBuf::from(id)));
+        }
+        env.extend(config.env_with_sources().await?.clone());
+        let venv = self.uv_venv().await;
+        if let Some(venv) = &venv {
+            for (k, v) in &venv.env {
+                env.insert(k.clone(), (v.clone(), venv.venv_path.clone()));
+            }
+        }
+        let (_, env_results) = self
+            .final_env_with_venv(config, venv.as_ref(), &env::PRISTINE_ENV)
+            .await?;
+        env.extend(env_results.env);
+        Ok(env.into_iter().map(|(k, (v, src))| (k, v, src)).collect())
+    }
+    /// the uv venv, which callers computing a whole env look up once and pass around
+    async fn uv_venv(&self) -> Option<uv::Venv> {
+        #[cfg(test)]
+        self.venv_lookups.fetch_add(1, Ordering::Relaxed);
+        uv::uv_venv().await
+    }
+    async fn env(&self, config: &Config, venv: Option<&uv::Venv>) -> Result<EnvMap> {
+        time!("env start");
+        let entries = self
+            .env_from_tools_strict(Settings::get().env_strict)
+            .await?
+            .into_iter()
+            .map(|(k, v, _)| (k, v))
+            .collect::<Vec<(String, String)>>();
+        let add_paths = entries
+            .iter()
+            .filter(|(k, _)| k == "MISE_ADD_PATH" || k == "RTX_ADD_PATH")
+            .map(|(_, v)| v)
+            .join(":");
+        let mut env: EnvMap = entries
+            .into_iter()
+            .filter(|(k, _)| k != "RTX_ADD_PATH")
+            .filter(|(k, _)| k != "MISE_ADD_PATH")
+            .filter(|(k, _)| !k.starts_with("RTX_TOOL_OPTS__"))
+            .filter(|(k, _)| !k.starts_with("MISE_TOOL_OPTS__"))
+            .rev()
+            .collect();
+        if !add_paths.is_empty() {
             env.insert(PATH_KEY.to_string(), add_paths);
         }
         env.extend(config.env().await?.clone());
-        if let Some(venv) = uv::uv_venv().await {
-            for (k, v) in venv.env {
-                env.insert(k, v);
-            }
+        if let Some(venv) = venv {
+            env.extend(venv.env.clone());
         }
+        expand_home_in_paths(&mut env);
         time!("env end");
         Ok(env)
     }
+    /// the tool, config, and post env. a var set to ENV_UNSET (`!unset`) is left out and added
+    /// to the results' env_remove, meaning it should be removed from the inherited env.
     pub async fn final_env(&self, config: &Config) -> Result<(EnvMap, EnvResults)> {
-        let mut env = self.env(config).await?;
-        let mut tera_env = env::PRISTINE_ENV.clone().into_iter().collect::<EnvMap>();
+        let venv = self.uv_venv().await;
+        self.final_env_with_venv(config, venv.as_ref(), &env::PRISTINE_ENV)
+            .await
+    }
+    /// env_with_path applied on top of `base` instead of the pristine process env. tool, config,
+    /// and post env vars override vars in `base` and tool paths are prepended to its PATH.
+    pub async fn final_env_over(&self, config: &Config, base: EnvMap) -> Result<EnvMap> {
+        let venv = self.uv_venv().await;
+        let (env, env_results) = self
+            .final_env_with_venv(config, venv.as_ref(), &base)
+            .await?;
+        let env_remove = env_results.env_remove.clone();
+        let base_path = base
+            .get(&*PATH_KEY)
+            .map(|p| env::split_paths(p).collect_vec());
+        let mut path_env = PathEnv::from_iter(base_path.unwrap_or_default());
+        for p in self
+            .list_final_paths_with_venv(config, env_results, venv.as_ref())
+            .await?
+        {
+            path_env.add(p);
+        }
+        let mut base = base;
+        base.retain(|k, _| !env_remove.contains(k));
+        base.extend(env);
+        base.insert(PATH_KEY.to_string(), path_env.to_string());
+        Ok(base)
+    }
+    async fn final_env_with_venv(
+        &self,
+        config: &Config,
+        venv: Option<&uv::Venv>,
+        base: &EnvMap,
+    ) -> Result<(EnvMap, EnvResults)> {
+        let mut env = self.env(config, venv).await?;
+        let mut tera_env = base.clone();
         tera_env.extend(env.clone());
-        let mut path_env = PathEnv::from_iter(env::PATH.clone());
-        for p in self.list_paths().await {
+        let base_path = base
+            .get(&*PATH_KEY)
+            .map(|p| env::split_paths(p).collect_vec());
+        let mut path_env = PathEnv::from_iter(base_path.unwrap_or_default());
+        for p in self.list_paths_strict(Settings::get().env_strict).await? {
             path_env.add(p);
         }
         for p in config.path_dirs().await?.clone() {
@@ -607,16 +1420,30 @@ impl Toolset {
         tera_env.insert(PATH_KEY.to_string(), path_env.to_string());
         let mut ctx = config.tera_ctx.clone();
         ctx.insert("env", &tera_env);
-        let env_results = self.load_post_env(config, ctx, &tera_env).await?;
+        ctx.insert("tools", &self.tools_tera_ctx());
+        let mut env_results = self.load_post_env(config, ctx, &tera_env).await?;
         env.extend(
             env_results
                 .env
                 .iter()
                 .map(|(k, v)| (k.clone(), v.0.clone())),
         );
+        expand_home_in_paths(&mut env);
+        hooks::run_env_finalize(config, &mut env).await?;
+        env.retain(|k, v| {
+            if v == ENV_UNSET {
+                env_results.env_remove.insert(k.clone());
+                return false;
+            }
+            true
+        });
         Ok((env, env_results))
     }
     pub async fn list_paths(&self) -> Vec<PathBuf> {
+        self.list_paths_strict(false).await.unwrap_or_default()
+    }
+    /// list_paths but when `strict` is set a failing list_bin_paths is an error instead of a warning
+    pub async fn list_paths_strict(&self, strict: bool) -> Result<Vec<PathBuf>> {
         let config = Config::get().await;
         let mut jset = JoinSet::new();
         for (i, (p, tv)) in self
@@ -625,26 +1452,31 @@ impl Toolset {
             .enumerate()
         {
             jset.spawn(async move {
-                p.list_bin_paths(&tv)
-                    .await
-                    .unwrap_or_else(|e| {
+                let paths = match p.list_bin_paths(&tv).await {
+                    Ok(paths) => paths,
+                    Err(e) if strict => {
+                        return Err(e.wrap_err(format!("failed to list bin paths for {tv}")));
+                    }
+                    Err(e) => {
                         warn!("Error listing bin paths for {tv}: {e:#}");
                         Vec::new()
-                    })
-                    .into_iter()
-                    .map(|p| (i, p))
-                    .collect::<Vec<_>>()
+                    }
+                };
+                Ok(paths.into_iter().map(|p| (i, p)).collect::<Vec<_>>())
             });
         }
 
-        jset.join_all()
+        Ok(jset
+            .join_all()
             .await
             .into_iter()
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
             .flatten()
             .sorted_by_key(|(i, _)| *i)
             .map(|(_, path)| path)
             .filter(|p| p.parent().is_some()) // TODO: why?
-            .collect()
+            .collect())
     }
     /// same as list_paths but includes config.list_paths, venv paths, and MISE_ADD_PATHs from self.env()
     pub async fn list_final_paths(
@@ -652,27 +1484,177 @@ impl Toolset {
         config: &Config,
         env_results: EnvResults,
     ) -> Result<Vec<PathBuf>> {
-        let mut paths = IndexSet::new();
-        for p in config.path_dirs().await?.clone() {
-            paths.insert(p);
+        if Settings::get().warn_system_shadows {
+            for (tv, bin, system_bin) in self.list_system_shadows().await {
+                warn_once!(
+                    "{tv} provides {} which shadows {}",
+                    display_path(&bin),
+                    display_path(&system_bin)
+                );
+            }
+        }
+        let venv = self.uv_venv().await;
+        self.list_final_paths_with_venv(config, env_results, venv.as_ref())
+            .await
+    }
+    /// bins provided by the current tools which have the same name as one on the system PATH,
+    /// along with the system bin they shadow
+    pub async fn list_system_shadows(&self) -> Vec<(ToolVersion, PathBuf, PathBuf)> {
+        self.system_shadows(&env::PATH).await
+    }
+    async fn system_shadows(
+        &self,
+        system_paths: &[PathBuf],
+    ) -> Vec<(ToolVersion, PathBuf, PathBuf)> {
+        let config = Config::get().await;
+        let mut found = vec![];
+        for (p, tv) in self.list_current_installed_versions(&config) {
+            let bin_paths = match p.list_bin_paths(&tv).await {
+                Ok(paths) => paths,
+                Err(e) => {
+                    debug!("Error listing bin paths for {tv}: {e:#}");
+                    continue;
+                }
+            };
+            // a tool bin dir that is already on the system PATH can't shadow itself
+            let system_paths = system_paths
+                .iter()
+                .filter(|p| !bin_paths.contains(p))
+                .cloned()
+                .collect_vec();
+            for bin in bin_paths
+                .iter()
+                .flat_map(|dir| file::ls(dir).unwrap_or_default())
+                .filter(|bin| file::is_executable(bin))
+            {
+                if let Some(system_bin) = file::which_in(bin.file_name().unwrap(), &system_paths) {
+                    found.push((tv.clone(), bin, system_bin));
+                }
+            }
         }
-        if let Some(venv) = uv::uv_venv().await {
-            paths.insert(venv.venv_path);
+        found
+    }
+    async fn list_final_paths_with_venv(
+        &self,
+        config: &Config,
+        env_results: EnvResults,
+        venv: Option<&uv::Venv>,
+    ) -> Result<Vec<PathBuf>> {
+        let raw = self.list_final_paths_raw(config, venv).await?;
+        let (paths, _) = final_paths(env_results.env_paths, raw, PathDedupe::FirstWins);
+        Ok(paths)
+    }
+    /// list_final_paths where `dedupe` picks which occurrence of a repeated dir is kept
+    pub async fn list_final_paths_with_dedupe(
+        &self,
+        config: &Config,
+        env_results: EnvResults,
+        dedupe: PathDedupe,
+    ) -> Result<Vec<PathBuf>> {
+        let venv = self.uv_venv().await;
+        let raw = self.list_final_paths_raw(config, venv.as_ref()).await?;
+        let (paths, _) = final_paths(env_results.env_paths, raw, dedupe);
+        Ok(paths)
+    }
+    /// list_final_paths with every duplicate removed, plus how many duplicates there were
+    pub async fn list_final_paths_with_dupes(
+        &self,
+        config: &Config,
+        env_results: EnvResults,
+    ) -> Result<(Vec<PathBuf>, usize)> {
+        let venv = self.uv_venv().await;
+        let raw = self.list_final_paths_raw(config, venv.as_ref()).await?;
+        Ok(final_paths(
+            env_results.env_paths,
+            raw,
+            PathDedupe::FirstWins,
+        ))
+    }
+    async fn list_final_paths_raw(
+        &self,
+        config: &Config,
+        venv: Option<&uv::Venv>,
+    ) -> Result<Vec<PathBuf>> {
+        #[cfg(test)]
+        self.final_path_lookups.fetch_add(1, Ordering::Relaxed);
+        let mut paths = vec![];
+        for p in config.path_dirs().await?.clone() {
+            paths.push(p);
         }
-        if let Some(path) = self.env(config).await?.get(&*PATH_KEY) {
-            paths.insert(PathBuf::from(path));
+        if let Some(venv) = venv {
+            paths.push(venv.venv_path.clone());
         }
-        for p in self.list_paths().await {
-            paths.insert(p);
+        if let Some(path) = self.env(config, venv).await?.get(&*PATH_KEY) {
+            paths.push(PathBuf::from(path));
         }
-        let mut path_env = PathEnv::from_iter(env::PATH.clone());
-        for p in paths.clone().into_iter() {
-            path_env.add(p);
+        for p in self.list_paths_strict(Settings::get().env_strict).await? {
+            paths.push(p);
         }
-        // these are returned in order, but we need to run the post_env stuff last and then put the results in the front
-        let paths = env_results.env_paths.into_iter().chain(paths).collect();
         Ok(paths)
     }
+    /// the resolved versions as a `[tools]` table which can be merged into a mise.toml to pin them
+    pub fn to_toml_tools(&self) -> toml::Value {
+        let mut tools = toml::Table::new();
+        for (ba, tvl) in &self.versions {
+            let mut versions = tvl.versions.iter().map(tool_version_to_toml).collect_vec();
+            let value = match versions.len() {
+                0 => continue,
+                1 => versions.pop().unwrap(),
+                _ => toml::Value::Array(versions),
+            };
+            tools.insert(ba.short.clone(), value);
+        }
+        let mut table = toml::Table::new();
+        table.insert("tools".into(), tools.into());
+        table.into()
+    }
+    /// a `shell.nix` with the mise env and PATH so nix users get the same tools. the pinned
+    /// versions are listed as comments since mise installs them rather than nixpkgs.
+    pub async fn to_nix_shell(&self, config: &Config) -> Result<String> {
+        let venv = self.uv_venv().await;
+        let (mut env, env_results) = self
+            .final_env_with_venv(config, venv.as_ref(), &env::PRISTINE_ENV)
+            .await?;
+        env.remove(&*PATH_KEY);
+        let paths = self
+            .list_final_paths_with_venv(config, env_results, venv.as_ref())
+            .await?;
+        let tools = self
+            .list_current_versions()
+            .into_iter()
+            .map(|(_, tv)| tv.to_string())
+            .collect_vec();
+        Ok(nix_shell(&tools, &paths, &env))
+    }
+    /// the mise env as a direnv `.envrc`, with vars as `export` lines and the tool paths as
+    /// `PATH_add` lines so direnv manages PATH itself
+    pub async fn to_direnv(&self, config: &Config) -> Result<String> {
+        let venv = self.uv_venv().await;
+        let (mut env, env_results) = self
+            .final_env_with_venv(config, venv.as_ref(), &env::PRISTINE_ENV)
+            .await?;
+        env.remove(&*PATH_KEY);
+        let paths = self
+            .list_final_paths_with_venv(config, env_results, venv.as_ref())
+            .await?;
+        Ok(direnv(&paths, &env))
+    }
+    /// a Dockerfile stage installing each pinned tool with `mise use -g`, in toolset order, with
+    /// the shims on PATH. expects `mise` to already be in the image.
+    pub fn to_dockerfile(&self) -> String {
+        let tools = self
+            .list_current_versions()
+            .into_iter()
+            .map(|(_, tv)| {
+                // the request's options, e.g. from `tool = { version = "1", foo = "bar" }`, aren't
+                // always on the backend arg
+                let mut ba = tv.ba().clone();
+                ba.opts = Some(tv.request.options());
+                format!("{}@{}", ba.full_with_opts(), tv.version)
+            })
+            .collect_vec();
+        dockerfile(&tools)
+    }
     pub async fn tera_ctx(&self) -> Result<&tera::Context> {
         self.tera_ctx
             .get_or_try_init(async || {
@@ -680,11 +1662,46 @@ impl Toolset {
                 let env = self.full_env(&config).await?;
                 let mut ctx = config.tera_ctx.clone();
                 ctx.insert("env", &env);
+                ctx.insert("tools", &self.tools_tera_ctx());
                 Ok(ctx)
             })
             .await
     }
+    /// `tools.<short>.version` and `tools.<short>.install_path` for templates, e.g.
+    /// `JAVA_HOME = { value = "{{ tools.java.install_path }}", tools = true }`
+    fn tools_tera_ctx(&self) -> BTreeMap<String, BTreeMap<&'static str, String>> {
+        let mut tools = BTreeMap::new();
+        // the first version of a tool is the one that comes first in PATH
+        for (_, tv) in self.list_current_versions() {
+            tools.entry(tv.ba().short.clone()).or_insert_with(|| {
+                BTreeMap::from([
+                    ("version", tv.version.clone()),
+                    (
+                        "install_path",
+                        tv.install_path().to_string_lossy().to_string(),
+                    ),
+                ])
+            });
+        }
+        tools
+    }
+    /// the current installed tool which provides bin_name. results are cached by bin name until
+    /// this toolset installs something or `clear_installed_cache` is called.
     pub async fn which(&self, bin_name: &str) -> Option<(Arc<dyn Backend>, ToolVersion)> {
+        if let Some(tv) = self.installed.which.get(bin_name).map(|tv| tv.clone()) {
+            let tv = tv?;
+            return tv.backend().ok().map(|p| (p, tv));
+        }
+        #[cfg(test)]
+        self.installed.which_misses.fetch_add(1, Ordering::Relaxed);
+        let found = self.which_uncached(bin_name).await;
+        self.installed.which.insert(
+            bin_name.to_string(),
+            found.as_ref().map(|(_, tv)| tv.clone()),
+        );
+        found
+    }
+    async fn which_uncached(&self, bin_name: &str) -> Option<(Arc<dyn Backend>, ToolVersion)> {
         let config = Config::get().await;
         for (p, tv) in self.list_current_installed_versions(&config) {
             match Box::pin(p.which(&tv, bin_name)).await {
@@ -697,10 +1714,133 @@ impl Toolset {
         }
         None
     }
+    /// forgets which versions are installed and which bins they provide, for callers that
+    /// install or uninstall outside of this toolset
+    pub fn clear_installed_cache(&self) {
+        self.installed.clear();
+    }
+    /// every installed tool which provides bin_name, in PATH precedence order so the winner is first
+    pub async fn which_all(&self, bin_name: &str) -> Vec<(Arc<dyn Backend>, ToolVersion)> {
+        self.which_with_shadowed(bin_name)
+            .await
+            .into_iter()
+            .map(|(p, tv, _)| (p, tv))
+            .collect()
+    }
+    /// like which_all but also returns the bin path of each candidate
+    /// the first entry is the one that wins, the rest are shadowed by it
+    pub async fn which_with_shadowed(
+        &self,
+        bin_name: &str,
+    ) -> Vec<(Arc<dyn Backend>, ToolVersion, PathBuf)> {
+        let config = Config::get().await;
+        let mut found = vec![];
+        for (p, tv) in self.list_current_installed_versions(&config) {
+            match Box::pin(p.which(&tv, bin_name)).await {
+                Ok(Some(bin)) => found.push((p, tv, bin)),
+                Ok(None) => {}
+                Err(e) => {
+                    debug!("Error running which: {:#}", e);
+                }
+            }
+        }
+        found
+    }
     pub async fn which_bin(&self, bin_name: &str) -> Option<PathBuf> {
         let (p, tv) = Box::pin(self.which(bin_name)).await?;
         Box::pin(p.which(&tv, bin_name)).await.ok().flatten()
     }
+    /// which_bin but searching only `paths`, in order, instead of the toolset's own bin paths.
+    /// for callers that have already computed a restricted PATH, e.g. a sandboxed `mise exec`.
+    pub fn which_in_paths(&self, bin_name: &str, paths: &[PathBuf]) -> Option<PathBuf> {
+        file::which_in(bin_name, paths)
+    }
+    /// which_bin with symlinks resolved, e.g. zig's `bin/zig -> ../zig` gives the real binary
+    pub async fn which_bin_canonical(&self, bin_name: &str) -> Option<PathBuf> {
+        let bin = self.which_bin(bin_name).await?;
+        match bin.canonicalize() {
+            Ok(bin) => Some(bin),
+            Err(err) => {
+                debug!("failed to resolve {}: {err}", display_path(&bin));
+                None
+            }
+        }
+    }
+    /// which_bin but when the shims dir is on PATH the shim is returned instead, since that is
+    /// what the shell runs. shims are kept in sync with the toolset by `config::rebuild_shims`.
+    pub async fn which_bin_with_shims(&self, bin_name: &str) -> Option<PathBuf> {
+        if env::PATH.iter().any(|p| p == *dirs::SHIMS) {
+            self.which_bin_in_shims(bin_name, &dirs::SHIMS).await
+        } else {
+            self.which_bin(bin_name).await
+        }
+    }
+    async fn which_bin_in_shims(&self, bin_name: &str, shims_dir: &Path) -> Option<PathBuf> {
+        let bin = self.which_bin(bin_name).await?;
+        // on windows shims have an extension
+        let shim = ["", ".exe", ".cmd", ".bat"]
+            .into_iter()
+            .map(|ext| shims_dir.join(format!("{bin_name}{ext}")))
+            .find(|p| p.is_file());
+        Some(shim.unwrap_or(bin))
+    }
+    /// which_bin but only looking in `version` of `short`, which must be a current installed version
+    pub async fn which_bin_for_version(
+        &self,
+        short: &str,
+        version: &str,
+        bin_name: &str,
+    ) -> Result<Option<PathBuf>> {
+        let config = Config::get().await;
+        let Some((p, tv)) = self
+            .list_current_installed_versions(&config)
+            .into_iter()
+            .find(|(p, tv)| p.ba().short == short && tv.version == version)
+        else {
+            bail!("{short}@{version} is not installed");
+        };
+        Box::pin(p.which(&tv, bin_name)).await
+    }
+    /// installed bins whose names contain `partial`, sorted by bin name
+    pub async fn which_fuzzy(&self, partial: &str) -> Vec<(Arc<dyn Backend>, ToolVersion, String)> {
+        let config = Config::get().await;
+        let mut found = vec![];
+        for (p, tv) in self.list_current_installed_versions(&config) {
+            let bin_paths = match p.list_bin_paths(&tv).await {
+                Ok(paths) => paths,
+                Err(e) => {
+                    debug!("Error listing bin paths for {tv}: {e:#}");
+                    continue;
+                }
+            };
+            for bin in bin_paths
+                .iter()
+                .flat_map(|dir| file::ls(dir).unwrap_or_default())
+                .filter(|bin| file::is_executable(bin))
+            {
+                let name = bin.file_name().unwrap().to_string_lossy().to_string();
+                if name.contains(partial) {
+                    found.push((p.clone(), tv.clone(), name));
+                }
+            }
+        }
+        found
+            .into_iter()
+            .sorted_by_cached_key(|(_, tv, name)| (name.clone(), tv.to_string()))
+            .dedup_by(|a, b| a.1 == b.1 && a.2 == b.2)
+            .collect()
+    }
+    /// which_bin plus the exec_env of the tool that provides it, for running the bin directly
+    pub async fn which_bin_with_env(&self, bin_name: &str) -> Option<(PathBuf, EnvMap)> {
+        let (p, tv) = Box::pin(self.which(bin_name)).await?;
+        let bin = Box::pin(p.which(&tv, bin_name)).await.ok().flatten()?;
+        let config = Config::get().await;
+        let env = p.exec_env(&config, self, &tv).await.unwrap_or_else(|e| {
+            warn!("Error running exec-env: {:#}", e);
+            EnvMap::new()
+        });
+        Some((bin, env))
+    }
     pub async fn install_missing_bin(
         &mut self,
         config: &Arc<Config>,
@@ -781,10 +1921,11 @@ impl Toolset {
     }
 
     fn is_disabled(&self, ba: &BackendArg) -> bool {
+        let settings = Settings::get();
         !ba.is_os_supported()
             || !tool_enabled(
-                &SETTINGS.enable_tools(),
-                &SETTINGS.disable_tools(),
+                &settings.enable_tools(),
+                &settings.disable_tools(),
                 &ba.short.to_string(),
             )
     }
@@ -795,9 +1936,8 @@ impl Toolset {
         ctx: tera::Context,
         env: &EnvMap,
     ) -> Result<EnvResults> {
-        let entries = config
-            .config_files
-            .iter()
+        let entries = config::env_config_files(&config.config_files)
+            .into_iter()
             .rev()
             .map(|(source, cf)| {
                 cf.env_entries()
@@ -828,6 +1968,240 @@ impl Toolset {
     }
 }
 
+/// runs `f` on every item with at most `jobs` running at once, results are in item order
+async fn join_bounded<I, T, F, Fut>(items: I, jobs: usize, f: F) -> Result<Vec<T>>
+where
+    I: IntoIterator,
+    F: Fn(I::Item) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut jset = JoinSet::new();
+    for (i, item) in items.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let fut = f(item);
+        jset.spawn(async move {
+            let _permit = semaphore.acquire().await?;
+            Ok((i, fut.await?))
+        });
+    }
+    Ok(jset
+        .join_all()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .sorted_by_key(|(i, _)| *i)
+        .map(|(_, t)| t)
+        .collect())
+}
+
+/// resolves what `install_some_versions` would install and reports each one without installing
+async fn plan_install(
+    config: &Arc<Config>,
+    queue: Vec<(Arc<dyn Backend>, Vec<ToolRequest>)>,
+    opts: &InstallOptions,
+    mpr: &MultiProgressReport,
+) -> Result<Vec<ToolVersion>> {
+    let mut planned = vec![];
+    for (ba, trs) in queue {
+        for tr in trs {
+            let tv = tr.resolve(config, &opts.resolve_options).await?;
+            if opts.force || !ba.is_version_installed(config, &tv, true) {
+                debug!("dry run: would install {tv}");
+                mpr.add(&tv.style())
+                    .finish_with_message("would install".into());
+                planned.push(tv);
+            }
+        }
+    }
+    Ok(planned)
+}
+
+/// errors before anything is downloaded when the backend expects `tv` to need more space than is
+/// free where it is installed
+async fn ensure_disk_space(
+    backend: &dyn Backend,
+    tv: &ToolVersion,
+    available_space: impl Fn(&Path) -> Option<u64>,
+) -> Result<()> {
+    let needed = match backend.estimated_install_size(tv).await {
+        Ok(Some(needed)) => needed,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            debug!("failed to estimate the install size of {tv}: {err:#}");
+            return Ok(());
+        }
+    };
+    let install_path = tv.install_path();
+    if let Some(free) = available_space(&install_path) {
+        if free < needed {
+            bail!(
+                "not enough disk space to install {tv}: it needs about {}MiB but {} only has {}MiB free",
+                needed / 1024 / 1024,
+                display_path(&install_path),
+                free / 1024 / 1024
+            );
+        }
+    }
+    Ok(())
+}
+
+/// runs the `verify_cmd` tool option against a fresh install of `tv` with the tool's env and
+/// PATH, e.g. `verify_cmd=node --version`
+async fn verify_install(
+    config: &Arc<Config>,
+    ts: &Toolset,
+    backend: &dyn Backend,
+    tv: &ToolVersion,
+    pr: &Box<dyn SingleReport>,
+) -> Result<()> {
+    let Some(verify_cmd) = tv.request.options().get("verify_cmd").cloned() else {
+        return Ok(());
+    };
+    pr.set_message(format!("verify {verify_cmd}"));
+    CmdLineRunner::new(&*env::SHELL)
+        .with_pr(pr)
+        .arg("-c")
+        .arg(&verify_cmd)
+        .envs(backend.exec_env(config, ts, tv).await?)
+        .env(&*PATH_KEY, backend.path_env_for_cmd(tv).await?)
+        .execute()
+        .wrap_err_with(|| format!("verify_cmd failed for {tv}: {verify_cmd}"))
+}
+
+/// collects results from `jset` until it is drained or `deadline` passes, at which point the
+/// remaining tasks are cancelled
+async fn join_until<T: 'static>(mut jset: JoinSet<Result<T>>, deadline: Instant) -> Result<Vec<T>> {
+    let mut results = vec![];
+    loop {
+        match tokio::time::timeout_at(deadline.into(), jset.join_next()).await {
+            Ok(Some(res)) => results.push(res??),
+            Ok(None) => break,
+            Err(_) => {
+                jset.abort_all();
+                break;
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// runs an install until it succeeds, fails with an error the backend does not consider
+/// retriable, or has been retried `retries` times. waits `delay` before the first retry and
+/// doubles it for each one after. `install` is given the attempt number, 0 for the first try.
+async fn with_install_retries<F, Fut>(
+    backend: &dyn Backend,
+    (retries, delay): (usize, Duration),
+    mut install: F,
+) -> Result<ToolVersion>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<ToolVersion>>,
+{
+    let mut attempt = 0;
+    loop {
+        match install(attempt).await {
+            Ok(tv) => return Ok(tv),
+            Err(err) if attempt < retries && backend.is_retriable_error(&err) => {
+                let delay = delay.saturating_mul(1 << attempt.min(16));
+                attempt += 1;
+                warn!(
+                    "retrying {} install in {delay:?} ({attempt}/{retries}): {err:#}",
+                    backend.id()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// a semaphore for each of `backend_types` which has a limit in `limits` (jobs_per_backend).
+/// backends without one are only bound by the global install semaphore.
+fn backend_semaphores(
+    backend_types: impl IntoIterator<Item = String>,
+    limits: &BTreeMap<String, usize>,
+) -> HashMap<String, Arc<Semaphore>> {
+    backend_types
+        .into_iter()
+        .filter_map(|t| {
+            let jobs = *limits.get(&t)?;
+            Some((t, Arc::new(Semaphore::new(jobs))))
+        })
+        .collect()
+}
+
+/// removes versions installed by a batch which then failed
+fn rollback_installs(tvs: &[ToolVersion]) {
+    for tv in tvs {
+        warn!("rolling back install of {tv}");
+        if let Err(err) = file::remove_all(tv.install_path()) {
+            warn!(
+                "failed to remove {}: {err:#}",
+                display_path(tv.install_path())
+            );
+        }
+    }
+    install_state::reset();
+}
+
+/// errors if the install of `tv` has not finished within `timeout`
+async fn with_install_timeout<Fut>(
+    backend: &dyn Backend,
+    tv: &ToolVersion,
+    timeout: Option<Duration>,
+    install: Fut,
+) -> Result<ToolVersion>
+where
+    Fut: Future<Output = Result<ToolVersion>>,
+{
+    let Some(timeout) = timeout else {
+        return install.await;
+    };
+    match tokio::time::timeout(timeout, install).await {
+        Ok(res) => res,
+        Err(_) => {
+            // the install was dropped partway through
+            backend.cleanup_install_dirs_on_error(tv);
+            bail!("timed out after {timeout:?}")
+        }
+    }
+}
+
+/// errors with a "did you mean" hint when a requested tool is unknown but close to one that isn't
+fn ensure_known_tools(versions: &[ToolRequest]) -> Result<()> {
+    for tr in versions {
+        let ba = tr.ba();
+        if ba.backend_type() != BackendType::Unknown {
+            continue;
+        }
+        if let Some(suggestion) = registry::suggest_short(&ba.short) {
+            bail!(
+                "{} is not a known tool, did you mean {}?",
+                style(&ba.short).yellow().for_stderr(),
+                style(suggestion).green().for_stderr()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn ensure_allowed_backends(versions: &[ToolRequest], allowed: &[String]) -> Result<()> {
+    for tr in versions {
+        let backend_type = tr.ba().backend_type();
+        if !allowed.iter().any(|b| b == backend_type.as_ref()) {
+            bail!(
+                "{} uses the {backend_type} backend which is not allowed, allowed backends: {}",
+                style(tr.ba().full()).yellow().for_stderr(),
+                allowed.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
 fn show_python_install_hint(versions: &[ToolRequest]) {
     let num_python = versions
         .iter()
@@ -843,41 +2217,254 @@ fn show_python_install_hint(versions: &[ToolRequest]) {
     );
 }
 
-impl Display for Toolset {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        let plugins = &self
-            .versions
-            .iter()
-            .map(|(_, v)| v.requests.iter().map(|tvr| tvr.to_string()).join(" "))
-            .collect_vec();
-        write!(f, "{}", plugins.join(", "))
+/// what the env of a directory is computed from: the config files that apply to it and the
+/// installed tools, with their mtimes so edits and reinstalls change the key
+fn dir_env_key<'a>(
+    config_files: impl IntoIterator<Item = &'a PathBuf>,
+    installed: &[ToolVersion],
+) -> String {
+    let mtime = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+    let config_files = config_files
+        .into_iter()
+        .map(|p| (p.clone(), mtime(p)))
+        .collect_vec();
+    let installed = installed
+        .iter()
+        .map(|tv| {
+            let path = tv.install_path();
+            (tv.ba().full(), tv.version.clone(), mtime(&path), path)
+        })
+        .collect_vec();
+    hash_to_str(&(config_files, installed))
+}
+
+/// the backends added, removed, and with different resolved versions between two toolsets
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToolsetDiff {
+    pub added: Vec<Arc<BackendArg>>,
+    pub removed: Vec<Arc<BackendArg>>,
+    /// the old and new resolved versions of backends in both toolsets
+    pub changed: Vec<(Arc<BackendArg>, Vec<String>, Vec<String>)>,
+}
+
+impl ToolsetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
     }
 }
 
-impl From<ToolRequestSet> for Toolset {
-    fn from(trs: ToolRequestSet) -> Self {
-        let mut ts = Toolset::default();
-        for (ba, versions, source) in trs.into_iter() {
-            ts.source = Some(source.clone());
-            let mut tvl = ToolVersionList::new(ba.clone(), source);
-            for tr in versions {
-                tvl.requests.push(tr);
+/// which occurrence of a dir that is in PATH more than once is kept
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PathDedupe {
+    #[default]
+    FirstWins,
+    /// keeps the last occurrence so a later tool's dir can move ahead of an earlier duplicate
+    LastWins,
+}
+
+impl PathDedupe {
+    pub fn apply(self, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        match self {
+            PathDedupe::FirstWins => paths.into_iter().unique().collect(),
+            PathDedupe::LastWins => {
+                let mut paths = paths.into_iter().rev().unique().collect_vec();
+                paths.reverse();
+                paths
             }
-            ts.versions.insert(ba, tvl);
         }
-        ts
     }
 }
 
-fn get_leaf_dependencies(requests: &[ToolRequest]) -> eyre::Result<Vec<ToolRequest>> {
-    // reverse maps potential shorts like "cargo-binstall" for "cargo:cargo-binstall"
-    let versions_hash = requests
-        .iter()
-        .flat_map(|tr| tr.ba().all_fulls())
-        .collect::<HashSet<_>>();
-    let leaves = requests
-        .iter()
-        .map(|tr| {
+/// the post env's paths in front of the deduped tool and config paths, plus how many duplicates
+/// were dropped. the post env runs last but its paths go first.
+fn final_paths(
+    env_paths: Vec<PathBuf>,
+    raw: Vec<PathBuf>,
+    dedupe: PathDedupe,
+) -> (Vec<PathBuf>, usize) {
+    let len = raw.len();
+    let paths = dedupe.apply(raw);
+    let dupes = len - paths.len();
+    (env_paths.into_iter().chain(paths).collect(), dupes)
+}
+
+/// expands a leading `~` in vars which hold paths like GOPATH or CARGO_HOME. vars ending in PATH
+/// are treated as lists so each entry is expanded.
+fn expand_home_in_paths(env: &mut EnvMap) {
+    for (k, v) in env.iter_mut() {
+        let key = k.to_uppercase();
+        if key.ends_with("PATH") {
+            let paths = std::env::split_paths(v.as_str())
+                .map(|p| expand_home(&p.to_string_lossy()))
+                .collect_vec();
+            if let Ok(joined) = std::env::join_paths(paths) {
+                *v = joined.to_string_lossy().to_string();
+            }
+        } else if ["HOME", "DIR", "ROOT"].iter().any(|s| key.ends_with(s)) {
+            *v = expand_home(v);
+        }
+    }
+}
+
+fn expand_home(s: &str) -> String {
+    match s {
+        "~" => dirs::HOME.to_string_lossy().to_string(),
+        s if s.starts_with("~/") => file::replace_path(s).to_string_lossy().to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// groups the requests by tool in a stable order regardless of how they were requested: highest
+/// `priority` first, then by tool short name and version. versions compare semantically, so 1.9.0
+/// comes before 1.10.0, and ones that don't parse like `latest` go first
+fn install_queue(versions: Vec<ToolRequest>) -> Vec<(Arc<BackendArg>, Vec<ToolRequest>)> {
+    versions
+        .into_iter()
+        .sorted_by_cached_key(|v| {
+            let version = v.version();
+            (v.ba().short.clone(), Versioning::new(&version), version)
+        })
+        .chunk_by(|v| v.ba().clone())
+        .into_iter()
+        .map(|(ba, v)| (ba, v.collect_vec()))
+        .sorted_by_key(|(_, trs)| std::cmp::Reverse(install_priority(&trs[0])))
+        .collect()
+}
+
+/// fills in the backend's default options beneath the ones already on `tr`
+fn apply_default_options(tr: &mut ToolRequest, backend: &dyn Backend) {
+    let defaults = backend.default_options();
+    if defaults.is_empty() {
+        return;
+    }
+    let mut options = tr.options();
+    options.merge(&defaults.opts);
+    for (k, v) in defaults.install_env {
+        options.install_env.entry(k).or_insert(v);
+    }
+    tr.set_options(options);
+}
+
+fn install_priority(tr: &ToolRequest) -> i64 {
+    match tr.options().get("priority") {
+        Some(p) => p.parse().unwrap_or_else(|_| {
+            warn!("invalid priority for {}: {p}", tr.ba());
+            0
+        }),
+        None => 0,
+    }
+}
+
+fn nix_shell(tools: &[String], paths: &[PathBuf], env: &EnvMap) -> String {
+    let mut out = String::from("# generated by mise\n");
+    for tool in tools {
+        out += &format!("# {tool}\n");
+    }
+    out += "{ pkgs ? import <nixpkgs> { } }:\n\npkgs.mkShell {\n";
+    for (k, v) in env {
+        out += &format!("  {} = {};\n", nix_str(k), nix_str(v));
+    }
+    if !paths.is_empty() {
+        let path = paths.iter().map(|p| p.to_string_lossy()).join(":");
+        // inside a nix '' string, '' and ${ need escaping
+        let path = shell_words::quote(&path)
+            .replace("''", "'''")
+            .replace("${", "''${");
+        out += &format!("  shellHook = ''\n    export PATH={path}:\"$PATH\"\n  '';\n");
+    }
+    out += "}\n";
+    out
+}
+
+fn direnv(paths: &[PathBuf], env: &EnvMap) -> String {
+    let mut out = String::from("# generated by mise\n");
+    for (k, v) in env {
+        out += &format!("export {k}={}\n", shell_words::quote(v));
+    }
+    // PATH_add prepends, so add the lowest-precedence dir first
+    for p in paths.iter().rev() {
+        out += &format!("PATH_add {}\n", shell_words::quote(&p.to_string_lossy()));
+    }
+    out
+}
+
+fn dockerfile(tools: &[String]) -> String {
+    let mut out = String::from("# generated by mise\n");
+    out += "ENV MISE_DATA_DIR=/mise\n";
+    out += "ENV PATH=\"/mise/shims:$PATH\"\n";
+    for tool in tools {
+        out += &format!("RUN mise use -g {}\n", shell_words::quote(tool));
+    }
+    out
+}
+
+fn nix_str(s: &str) -> String {
+    let s = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+    format!("\"{s}\"")
+}
+
+fn tool_version_to_toml(tv: &ToolVersion) -> toml::Value {
+    let opts = tv.request.options();
+    if opts.is_empty() && opts.os.is_none() {
+        return tv.version.clone().into();
+    }
+    let mut table = toml::Table::new();
+    table.insert("version".into(), tv.version.clone().into());
+    for (k, v) in opts.opts {
+        table.insert(k, v.into());
+    }
+    if let Some(os) = opts.os {
+        table.insert("os".into(), os.into());
+    }
+    if !opts.install_env.is_empty() {
+        let env: toml::Table = opts
+            .install_env
+            .into_iter()
+            .map(|(k, v)| (k, v.into()))
+            .collect();
+        table.insert("install_env".into(), env.into());
+    }
+    table.into()
+}
+
+impl Display for Toolset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let plugins = &self
+            .versions
+            .iter()
+            .map(|(_, v)| v.requests.iter().map(|tvr| tvr.to_string()).join(" "))
+            .collect_vec();
+        write!(f, "{}", plugins.join(", "))
+    }
+}
+
+impl From<ToolRequestSet> for Toolset {
+    fn from(trs: ToolRequestSet) -> Self {
+        let mut ts = Toolset::default();
+        for (ba, versions, source) in trs.into_iter() {
+            ts.source = Some(source.clone());
+            let mut tvl = ToolVersionList::new(ba.clone(), source);
+            for tr in versions {
+                tvl.requests.push(tr);
+            }
+            ts.versions.insert(ba, tvl);
+        }
+        ts
+    }
+}
+
+fn get_leaf_dependencies(requests: &[ToolRequest]) -> eyre::Result<Vec<ToolRequest>> {
+    // reverse maps potential shorts like "cargo-binstall" for "cargo:cargo-binstall"
+    let versions_hash = requests
+        .iter()
+        .flat_map(|tr| tr.ba().all_fulls())
+        .collect::<HashSet<_>>();
+    let leaves = requests
+        .iter()
+        .map(|tr| {
             match tr.backend()?.get_all_dependencies(true)?.iter().all(|dep| {
                 // dep is a dependency of tr so if it is in versions_hash (meaning it's also being installed) then it is not a leaf node
                 !dep.all_fulls()
@@ -898,10 +2485,17 @@ type TVTuple = (Arc<dyn Backend>, ToolVersion);
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use pretty_assertions::assert_eq;
     use test_log::test;
 
-    use super::ToolVersionOptions;
+    use super::*;
+    use crate::config::config_file::ConfigFile;
+    use crate::config::config_file::mise_toml::MiseToml;
+    use crate::config::settings::SettingsPartial;
+    use crate::file;
+    use confique::Partial;
     #[test]
     fn test_tool_version_options() {
         let t = |input, f| {
@@ -932,5 +2526,1742 @@ mod tests {
                 ..Default::default()
             },
         );
+        t(
+            r#"exe="foo,bar",match=musl"#,
+            ToolVersionOptions {
+                opts: [
+                    ("exe".to_string(), "foo,bar".to_string()),
+                    ("match".to_string(), "musl".to_string()),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                ..Default::default()
+            },
+        );
+        t(
+            r#"exe="foo,bar"#,
+            ToolVersionOptions {
+                opts: [
+                    ("exe".to_string(), r#""foo"#.to_string()),
+                    ("bar".to_string(), "".to_string()),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                ..Default::default()
+            },
+        );
+        t(
+            r"postinstall=echo\,done,a\=b=c",
+            ToolVersionOptions {
+                opts: [
+                    ("postinstall".to_string(), "echo,done".to_string()),
+                    ("a=b".to_string(), "c".to_string()),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                ..Default::default()
+            },
+        );
+        t(
+            r#"exe="a\"b,c",dir=C:\tools"#,
+            ToolVersionOptions {
+                opts: [
+                    ("exe".to_string(), r#"a"b,c"#.to_string()),
+                    ("dir".to_string(), r"C:\tools".to_string()),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_tool_version_options_merge_with() {
+        let other = BTreeMap::from([
+            ("exe".to_string(), "cli".to_string()),
+            ("match".to_string(), "musl".to_string()),
+        ]);
+        let mut keep = parse_tool_options("exe=toml");
+        keep.merge(&other);
+        assert_eq!(keep.get("exe").unwrap(), "toml");
+        assert_eq!(keep.get("match").unwrap(), "musl");
+        let mut overwrite = parse_tool_options("exe=toml");
+        overwrite.merge_with(&other, MergeStrategy::Overwrite);
+        assert_eq!(overwrite.get("exe").unwrap(), "cli");
+        assert_eq!(overwrite.get("match").unwrap(), "musl");
+    }
+
+    #[test]
+    fn test_init_request_options() {
+        let ba: Arc<BackendArg> = Arc::new("tiny".into());
+        let opts = parse_tool_options("exe=tiny");
+        let mut ts = Toolset::new(ToolSource::Argument);
+        ts.add_version(
+            ToolRequest::new_opts(ba.clone(), "3", opts.clone(), ToolSource::Argument).unwrap(),
+        );
+
+        let unset = ToolRequest::new(ba, "3", ToolSource::Argument).unwrap();
+        assert_eq!(unset.explicit_options(), None);
+        let cleared_ba: Arc<BackendArg> = Arc::new("tiny[]".into());
+        assert_eq!(cleared_ba.short, "tiny");
+        let cleared = ToolRequest::new(cleared_ba, "3", ToolSource::Argument).unwrap();
+        assert_eq!(cleared.explicit_options(), Some(&Default::default()));
+
+        let mut requests = vec![unset, cleared];
+        ts.init_request_options(&mut requests);
+        assert_eq!(requests[0].options().opts, opts.opts);
+        assert!(requests[1].options().is_empty());
+    }
+
+    #[test]
+    fn test_to_toml_tools() {
+        let ba: Arc<BackendArg> = Arc::new("tiny".into());
+        let opts = parse_tool_options("exe=tiny,match=musl");
+        let tr =
+            ToolRequest::new_opts(ba.clone(), "3", opts.clone(), ToolSource::Argument).unwrap();
+        let mut ts = Toolset::new(ToolSource::Argument);
+        ts.add_version(tr.clone());
+        ts.versions
+            .get_mut(&ba)
+            .unwrap()
+            .versions
+            .push(ToolVersion::new(tr, "3.1.0".into()));
+        let body = toml::to_string(&ts.to_toml_tools()).unwrap();
+        assert_eq!(
+            body,
+            "[tools.tiny]\nexe = \"tiny\"\nmatch = \"musl\"\nversion = \"3.1.0\"\n"
+        );
+        let cf = MiseToml::from_str(&body, std::path::Path::new("mise.toml")).unwrap();
+        let trs = cf.to_tool_request_set().unwrap();
+        let requests = trs.tools.get(&ba).unwrap();
+        assert_eq!(requests[0].version(), "3.1.0");
+        assert_eq!(requests[0].options().opts, opts.opts);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_expand_home_in_paths() {
+        let home = dirs::HOME.to_path_buf();
+        let mut env = EnvMap::from([
+            ("GOPATH".to_string(), "~/go".to_string()),
+            ("CARGO_HOME".to_string(), "~".to_string()),
+            ("MANPATH".to_string(), "/usr/share/man:~/man".to_string()),
+            ("GREETING".to_string(), "~/hi".to_string()),
+        ]);
+        expand_home_in_paths(&mut env);
+        let path = |p: &str| home.join(p).to_string_lossy().to_string();
+        assert_eq!(env["GOPATH"], path("go"));
+        assert_eq!(env["CARGO_HOME"], home.to_string_lossy());
+        assert_eq!(env["MANPATH"], format!("/usr/share/man:{}", path("man")));
+        assert_eq!(env["GREETING"], "~/hi");
+    }
+
+    #[test]
+    fn test_list_own_requests() {
+        let project = PathBuf::from("/work/project");
+        let mut ts = Toolset::new(ToolSource::Argument);
+        for (short, config) in [
+            ("tiny", project.join("mise.toml")),
+            ("dummy", PathBuf::from("/work/mise.toml")),
+            ("node", project.join(".tool-versions")),
+        ] {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            let source = match config.file_name().unwrap().to_str() {
+                Some("mise.toml") => ToolSource::MiseToml(config),
+                _ => ToolSource::ToolVersions(config),
+            };
+            ts.add_version(ToolRequest::new(ba, "1", source).unwrap());
+        }
+        let own = ts.list_own_requests(|s| s.path().and_then(|p| p.parent()) == Some(&project));
+        assert_eq!(
+            own.iter().map(|tr| tr.ba().short.as_str()).collect_vec(),
+            vec!["tiny", "node"]
+        );
+        assert_eq!(ts.list_own_requests(|_| true).len(), 3);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_list_final_paths_with_dupes() {
+        // both tools are installed into the same dir so they contribute the same bin path
+        let tmp = tempfile::tempdir().unwrap();
+        let shared = tmp.path().join("bin");
+        file::create_dir_all(&shared).unwrap();
+        file::write(tmp.path().join("VERSION"), "1.0.0").unwrap();
+        let tvs = ["tiny", "dummy"].map(|short| {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            let opts = parse_tool_options(&format!("install_path={}", tmp.path().display()));
+            let tr = ToolRequest::new_opts(ba, "1.0.0", opts, ToolSource::Argument).unwrap();
+            ToolVersion::new(tr, "1.0.0".into())
+        });
+        let ts = toolset_with(tvs.to_vec());
+        let config = Config::get().await;
+        let (_, env_results) = ts.final_env(&config).await.unwrap();
+        let (paths, dupes) = ts
+            .list_final_paths_with_dupes(&config, env_results)
+            .await
+            .unwrap();
+        assert_eq!(dupes, 1);
+        assert_eq!(paths.iter().filter(|p| **p == shared).count(), 1);
+    }
+
+    #[test]
+    fn test_install_queue() {
+        let tr = |short: &str, opts: &str| {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            ToolRequest::new_opts(ba, "1", parse_tool_options(opts), ToolSource::Argument).unwrap()
+        };
+        let queue = |versions: Vec<ToolRequest>| {
+            install_queue(versions)
+                .into_iter()
+                .map(|(ba, _)| ba.short.clone())
+                .collect_vec()
+        };
+        assert_eq!(
+            queue(vec![tr("tiny", ""), tr("dummy", ""), tr("node", "")]),
+            vec!["dummy", "node", "tiny"]
+        );
+        assert_eq!(
+            queue(vec![tr("node", ""), tr("tiny", ""), tr("dummy", "")]),
+            vec!["dummy", "node", "tiny"]
+        );
+        assert_eq!(
+            queue(vec![
+                tr("tiny", "priority=10"),
+                tr("dummy", ""),
+                tr("node", "priority=-1"),
+            ]),
+            vec!["tiny", "dummy", "node"]
+        );
+
+        let versions = |versions: &[&str]| {
+            let ba: Arc<BackendArg> = Arc::new("tiny".into());
+            let trs = versions
+                .iter()
+                .map(|v| ToolRequest::new(ba.clone(), v, ToolSource::Argument).unwrap())
+                .collect();
+            install_queue(trs)[0]
+                .1
+                .iter()
+                .map(|tr| tr.version())
+                .collect_vec()
+        };
+        assert_eq!(
+            versions(&["1.10.0", "latest", "1.9.0", "2"]),
+            vec!["latest", "1.9.0", "1.10.0", "2"]
+        );
+    }
+
+    #[test]
+    fn test_nix_shell() {
+        let env = EnvMap::from([("GREETING".to_string(), "say \"hi\" ${USER}".to_string())]);
+        let nix = nix_shell(
+            &["tiny@4.0.0".to_string()],
+            &[PathBuf::from("/mise/installs/tiny/4.0.0/bin")],
+            &env,
+        );
+        assert_eq!(
+            nix,
+            r#"# generated by mise
+# tiny@4.0.0
+{ pkgs ? import <nixpkgs> { } }:
+
+pkgs.mkShell {
+  "GREETING" = "say \"hi\" \${USER}";
+  shellHook = ''
+    export PATH=/mise/installs/tiny/4.0.0/bin:"$PATH"
+  '';
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_direnv() {
+        let env = EnvMap::from([("GREETING".to_string(), "say hi".to_string())]);
+        let envrc = direnv(
+            &[
+                PathBuf::from("/mise/installs/tiny/4.0.0/bin"),
+                PathBuf::from("/mise/installs/dummy/1.0.0/bin"),
+            ],
+            &env,
+        );
+        assert_eq!(
+            envrc,
+            r#"# generated by mise
+export GREETING='say hi'
+PATH_add /mise/installs/dummy/1.0.0/bin
+PATH_add /mise/installs/tiny/4.0.0/bin
+"#
+        );
+    }
+
+    #[test]
+    fn test_to_dockerfile() {
+        let tv = |short: &str, version: &str, opts: &str| {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            let tr =
+                ToolRequest::new_opts(ba, version, parse_tool_options(opts), ToolSource::Argument)
+                    .unwrap();
+            ToolVersion::new(tr, version.into())
+        };
+        let ts = toolset_with(vec![
+            tv("tiny", "4.8.0", ""),
+            tv("dummy", "4.0.0", "foo=bar"),
+        ]);
+        assert_eq!(
+            ts.to_dockerfile(),
+            r#"# generated by mise
+ENV MISE_DATA_DIR=/mise
+ENV PATH="/mise/shims:$PATH"
+RUN mise use -g asdf:tiny@4.8.0
+RUN mise use -g 'asdf:dummy[foo=bar]@4.0.0'
+"#
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_to_nix_shell() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.8.0", &["tiny-bin"]);
+        file::write(tv.install_path().join("VERSION"), "4.8.0").unwrap();
+        let bin = tv.install_path().join("bin");
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let nix = ts.to_nix_shell(&config).await.unwrap();
+        assert!(nix.contains("# asdf:tiny@4.8.0\n"), "{nix}");
+        assert!(nix.contains(&*bin.to_string_lossy()), "{nix}");
+        assert!(nix.contains("\"JDXCODE_TINY\" = \"4.8.0\";"), "{nix}");
+        assert!(nix.contains("\"TEST_ENV_VAR\" = \"test-123\";"), "{nix}");
+    }
+
+    /// tool versions installed by a test. each one goes into its own dir under a tempdir through
+    /// the `install_path` option, and its cache dir (incomplete markers, exec-env) is removed with
+    /// the tempdir on drop so tests don't see each other's installs
+    struct TestInstalls {
+        dir: tempfile::TempDir,
+        cache_paths: Mutex<Vec<PathBuf>>,
+    }
+
+    impl TestInstalls {
+        fn new() -> Self {
+            Self {
+                dir: tempfile::tempdir().unwrap(),
+                cache_paths: Default::default(),
+            }
+        }
+
+        fn path(&self) -> &Path {
+            self.dir.path()
+        }
+
+        /// a request for `short@version` installing into this dir, `opts` are appended to the
+        /// tool options, e.g. `,verify_cmd=false`
+        fn request(&self, short: &str, version: &str, opts: &str) -> ToolRequest {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            let opts = parse_tool_options(&format!(
+                "install_path={}{opts}",
+                self.path().join(short).join(version).display()
+            ));
+            let tr = ToolRequest::new_opts(ba, version, opts, ToolSource::Argument).unwrap();
+            let tv = ToolVersion::new(tr.clone(), version.into());
+            self.cache_paths.lock().unwrap().push(tv.cache_path());
+            tr
+        }
+
+        /// an installed-looking version of a tool with the given executables in its bin dir
+        fn fake_install(&self, short: &str, version: &str, bins: &[&str]) -> ToolVersion {
+            let tv = ToolVersion::new(self.request(short, version, ""), version.into());
+            let bin_dir = tv.install_path().join("bin");
+            file::create_dir_all(&bin_dir).unwrap();
+            for bin in bins {
+                file::write(bin_dir.join(bin), "#!/bin/sh\n").unwrap();
+                file::make_executable(bin_dir.join(bin)).unwrap();
+            }
+            tv
+        }
+    }
+
+    impl Drop for TestInstalls {
+        fn drop(&mut self) {
+            for path in self.cache_paths.lock().unwrap().iter() {
+                let _ = file::remove_all(path);
+            }
+        }
+    }
+
+    fn toolset_with(tvs: Vec<ToolVersion>) -> Toolset {
+        let mut ts = Toolset::new(ToolSource::Argument);
+        for tv in tvs {
+            ts.add_version(tv.request.clone());
+            ts.versions.get_mut(tv.ba()).unwrap().versions.push(tv);
+        }
+        ts
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_with_shadowed() {
+        let installs = TestInstalls::new();
+        let ts = toolset_with(vec![
+            installs.fake_install("tiny", "4.0.0", &["shadowed-bin"]),
+            installs.fake_install("dummy", "4.0.0", &["shadowed-bin"]),
+        ]);
+        let found = ts.which_with_shadowed("shadowed-bin").await;
+        let found = found
+            .iter()
+            .map(|(p, tv, bin)| (p.id().to_string(), tv.version.clone(), bin.clone()))
+            .collect_vec();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "tiny".to_string(),
+                    "4.0.0".to_string(),
+                    installs.path().join("tiny/4.0.0/bin/shadowed-bin")
+                ),
+                (
+                    "dummy".to_string(),
+                    "4.0.0".to_string(),
+                    installs.path().join("dummy/4.0.0/bin/shadowed-bin")
+                ),
+            ]
+        );
+        let (winner, _) = ts.which("shadowed-bin").await.unwrap();
+        assert_eq!(winner.id(), "tiny");
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_installed_checked_once_per_version() {
+        let installs = TestInstalls::new();
+        let tiny = installs.fake_install("tiny", "4.7.0", &["tiny-bin"]);
+        file::write(tiny.install_path().join("VERSION"), "4.7.0").unwrap();
+        let ts = toolset_with(vec![
+            tiny,
+            installs.fake_install("dummy", "4.7.0", &["dummy-bin"]),
+        ]);
+        let config = Config::get().await;
+        ts.env_with_path(&config).await.unwrap();
+        ts.which("tiny-bin").await.unwrap();
+        assert_eq!(ts.installed.misses.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_cached() {
+        let installs = TestInstalls::new();
+        let ts = toolset_with(vec![installs.fake_install(
+            "tiny",
+            "4.19.0",
+            &["tiny-cached"],
+        )]);
+        let (p, tv) = ts.which("tiny-cached").await.unwrap();
+        assert_eq!((p.id(), tv.version.as_str()), ("tiny", "4.19.0"));
+        assert!(ts.which("tiny-missing").await.is_none());
+        assert_eq!(ts.installed.which_misses.load(Ordering::Relaxed), 2);
+
+        let (_, tv) = ts.which("tiny-cached").await.unwrap();
+        assert_eq!(tv.version, "4.19.0");
+        assert!(ts.which("tiny-missing").await.is_none());
+        assert_eq!(ts.installed.which_misses.load(Ordering::Relaxed), 2);
+
+        ts.clear_installed_cache();
+        ts.which("tiny-cached").await.unwrap();
+        assert_eq!(ts.installed.which_misses.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_bin_in_shims() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.11.0", &["tiny-shimmed"]);
+        let bin = tv.install_path().join("bin/tiny-shimmed");
+        let ts = toolset_with(vec![tv]);
+        let shims = tempfile::tempdir().unwrap();
+        assert_eq!(
+            ts.which_bin_in_shims("tiny-shimmed", shims.path()).await,
+            Some(bin)
+        );
+
+        let shim = shims.path().join("tiny-shimmed");
+        file::write(&shim, "").unwrap();
+        assert_eq!(
+            ts.which_bin_in_shims("tiny-shimmed", shims.path()).await,
+            Some(shim)
+        );
+        // a shim alone is not enough, a tool has to provide the bin
+        file::write(shims.path().join("missing"), "").unwrap();
+        assert_eq!(ts.which_bin_in_shims("missing", shims.path()).await, None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_which_in_paths() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.15.0", &["tiny-restricted"]);
+        let bin_dir = tv.install_path().join("bin");
+        let ts = toolset_with(vec![tv]);
+        let other = tempfile::tempdir().unwrap();
+        assert_eq!(
+            ts.which_in_paths(
+                "tiny-restricted",
+                &[other.path().to_path_buf(), bin_dir.clone()]
+            ),
+            Some(bin_dir.join("tiny-restricted"))
+        );
+        // the toolset provides the bin but it is not in `paths`
+        assert_eq!(
+            ts.which_in_paths("tiny-restricted", &[other.path().to_path_buf()]),
+            None
+        );
+        // bins outside the toolset are found too as long as they are in `paths`
+        let outside = other.path().join("outside");
+        file::write(&outside, "#!/bin/sh\n").unwrap();
+        file::make_executable(&outside).unwrap();
+        assert_eq!(
+            ts.which_in_paths("outside", &[other.path().to_path_buf()]),
+            Some(outside)
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_system_shadows() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.16.0", &["ls", "tiny-only"]);
+        let bin = tv.install_path().join("bin");
+        let ts = toolset_with(vec![tv]);
+        let system = tempfile::tempdir().unwrap();
+        let system_ls = system.path().join("ls");
+        file::write(&system_ls, "#!/bin/sh\n").unwrap();
+        file::make_executable(&system_ls).unwrap();
+        let shadows = ts
+            .system_shadows(&[system.path().to_path_buf(), bin.clone()])
+            .await
+            .into_iter()
+            .map(|(tv, bin, system_bin)| (tv.version, bin, system_bin))
+            .collect_vec();
+        assert_eq!(
+            shadows,
+            vec![("4.16.0".to_string(), bin.join("ls"), system_ls)]
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_bin_canonical() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("zig", "0.13.0", &[]);
+        let zig = tv.install_path().join("zig");
+        file::write(&zig, "#!/bin/sh\n").unwrap();
+        file::make_executable(&zig).unwrap();
+        file::make_symlink(Path::new("../zig"), &tv.install_path().join("bin/zig")).unwrap();
+        let ts = toolset_with(vec![tv]);
+        assert_eq!(
+            ts.which_bin_canonical("zig").await,
+            Some(zig.canonicalize().unwrap())
+        );
+
+        file::remove_file(&zig).unwrap();
+        assert_eq!(ts.which_bin_canonical("zig").await, None);
+        file::remove_all(zig.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_fuzzy() {
+        let installs = TestInstalls::new();
+        let ts = toolset_with(vec![
+            installs.fake_install("python", "3.12.0", &["python", "pytest", "pip"]),
+            installs.fake_install("tiny", "4.7.0", &["rtx-tiny"]),
+        ]);
+        let found = ts
+            .which_fuzzy("py")
+            .await
+            .into_iter()
+            .map(|(p, tv, bin)| format!("{}@{} {bin}", p.id(), tv.version))
+            .collect_vec();
+        assert_eq!(found, vec!["python@3.12.0 pytest", "python@3.12.0 python"]);
+        assert!(ts.which_fuzzy("nope").await.is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_bin_for_version() {
+        let installs = TestInstalls::new();
+        let tvs = vec![
+            installs.fake_install("node", "20.0.0", &["node"]),
+            installs.fake_install("node", "22.0.0", &["node"]),
+        ];
+        let expected = tvs[1].install_path().join("bin/node");
+        let ts = toolset_with(tvs);
+        assert_eq!(
+            ts.which_bin_for_version("node", "22.0.0", "node")
+                .await
+                .unwrap(),
+            Some(expected)
+        );
+        assert_eq!(
+            ts.which_bin_for_version("node", "22.0.0", "missing-bin")
+                .await
+                .unwrap(),
+            None
+        );
+        let err = ts
+            .which_bin_for_version("node", "18.0.0", "node")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "node@18.0.0 is not installed");
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_which_bin_with_env() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.0.0", &["env-bin"]);
+        file::write(tv.install_path().join("VERSION"), "4.0.0").unwrap();
+        let ts = toolset_with(vec![tv]);
+        let (bin, env) = ts.which_bin_with_env("env-bin").await.unwrap();
+        assert_eq!(bin, installs.path().join("tiny/4.0.0/bin/env-bin"));
+        assert_eq!(env.get("JDXCODE_TINY").unwrap(), "4.0.0");
+        assert!(ts.which_bin_with_env("missing-bin").await.is_none());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_upgrade_outdated() {
+        let ba: Arc<BackendArg> = Arc::new("tiny".into());
+        let tr = ToolRequest::new(ba.clone(), "2", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "2.0.0".into());
+        file::create_dir_all(tv.install_path().join("bin")).unwrap();
+        let latest = ToolVersion::new(
+            ToolRequest::new(ba, "2.1.0", ToolSource::Argument).unwrap(),
+            "2.1.0".into(),
+        );
+        let _ = file::remove_all(latest.install_path());
+        let mut ts = toolset_with(vec![tv.clone()]);
+        let config = Config::get().await;
+
+        let installed = ts
+            .upgrade_outdated(&config, false, &InstallOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            installed.iter().map(|tv| tv.version.clone()).collect_vec(),
+            vec!["2.1.0"]
+        );
+        assert_eq!(
+            file::read_to_string(latest.install_path().join("VERSION"))
+                .unwrap()
+                .trim(),
+            "2.1.0"
+        );
+        // upgrades install into the installs dir so they are removed here
+        for tv in [&tv, &latest] {
+            file::remove_all(tv.install_path()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_path_option() {
+        let tmp = tempfile::tempdir().unwrap();
+        let install_path = tmp.path().join("tiny-1.0.0");
+        let ba: Arc<BackendArg> = Arc::new("tiny".into());
+        let mut tr = ToolRequest::new(ba.clone(), "1.0.0", ToolSource::Argument).unwrap();
+        tr.set_options(parse_tool_options(&format!(
+            "install_path={}",
+            install_path.display()
+        )));
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        let installed = ts
+            .install_all_versions(&config, vec![tr], &InstallOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(installed[0].install_path(), install_path);
+        assert_eq!(
+            file::read_to_string(install_path.join("VERSION"))
+                .unwrap()
+                .trim(),
+            "1.0.0"
+        );
+
+        let mut tr = ToolRequest::new(ba, "1.0.0", ToolSource::Argument).unwrap();
+        tr.set_options(parse_tool_options("install_path=relative/tiny"));
+        let err = ToolVersion::resolve(&config, tr, &Default::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "install_path for tiny must be an absolute path: relative/tiny"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_capture_logs() {
+        let installs = TestInstalls::new();
+        let tr = installs.request("tiny", "1.2.0", "");
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        let opts = InstallOptions {
+            capture_logs: true,
+            ..Default::default()
+        };
+        let installed = ts
+            .install_all_versions(&config, vec![tr.clone()], &opts)
+            .await
+            .unwrap();
+        let log = opts.records.get(&installed[0]).unwrap().log;
+        assert!(log.contains(&"bin/install".to_string()), "{log:?}");
+
+        let opts = InstallOptions {
+            force: true,
+            ..Default::default()
+        };
+        let installed = ts
+            .install_all_versions(&config, vec![tr], &opts)
+            .await
+            .unwrap();
+        assert!(opts.records.get(&installed[0]).unwrap().log.is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_order() {
+        let installs = TestInstalls::new();
+        let trs = vec![
+            installs.request("tiny", "1.4.0", ""),
+            installs.request("dummy", "1.0.0", ""),
+        ];
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        let installed = ts
+            .install_all_versions(&config, trs, &InstallOptions::default())
+            .await
+            .unwrap();
+        // returned in install_queue order however the installs finish
+        let shorts = installed
+            .iter()
+            .map(|tv| tv.ba().short.clone())
+            .collect_vec();
+        assert_eq!(shorts, vec!["dummy", "tiny"]);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_json_events() {
+        let installs = TestInstalls::new();
+        let tr = installs.request("tiny", "1.3.0", "");
+        let sink = Arc::new(Mutex::new(vec![]));
+        let opts = InstallOptions {
+            reporter: InstallReporter::Json(sink.clone()),
+            ..Default::default()
+        };
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        ts.install_all_versions(&config, vec![tr], &opts)
+            .await
+            .unwrap();
+        let out = String::from_utf8(sink.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let first = events.first().unwrap();
+        assert_eq!(first["event"], "start");
+        assert_eq!(first["tool"], "tiny");
+        assert_eq!(first["version"], "1.3.0");
+        assert!(
+            events
+                .iter()
+                .any(|e| e["event"] == "progress" && e["message"] == "bin/install")
+        );
+        let last = events.last().unwrap();
+        assert_eq!(last["event"], "success");
+        assert!(last["elapsed_ms"].is_u64());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_duration() {
+        let installs = TestInstalls::new();
+        let tr = installs.request("tiny", "2.0.0", "");
+        let opts = InstallOptions::default();
+        let tv = ToolVersion::new(tr.clone(), "2.0.0".into());
+        assert_eq!(opts.records.get(&tv), None);
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        let installed = ts
+            .install_all_versions(&config, vec![tr], &opts)
+            .await
+            .unwrap();
+        let record = opts.records.get(&installed[0]).unwrap();
+        assert!(record.duration > std::time::Duration::ZERO);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_dry_run() {
+        let installs = TestInstalls::new();
+        let tr = installs.request("tiny", "1.1.0", "");
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        let opts = InstallOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let planned = ts
+            .install_all_versions(&config, vec![tr], &opts)
+            .await
+            .unwrap();
+        assert_eq!(
+            planned.iter().map(|tv| tv.to_string()).collect_vec(),
+            vec!["asdf:tiny@1.1.0"]
+        );
+        assert!(!installs.path().join("tiny").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_allowed_backends() {
+        let installs = TestInstalls::new();
+        let tiny = installs.request("tiny", "1.1.0", "");
+        let cargo: Arc<BackendArg> = Arc::new("cargo:eza".into());
+        let cargo = ToolRequest::new(cargo, "0.20.0", ToolSource::Argument).unwrap();
+        let mut ts = Toolset::new(ToolSource::Argument);
+        let config = Config::get().await;
+        let opts = InstallOptions {
+            dry_run: true,
+            allowed_backends: Some(vec!["core".to_string(), "asdf".to_string()]),
+            ..Default::default()
+        };
+        let err = ts
+            .install_all_versions(&config, vec![tiny.clone(), cargo], &opts)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            console::strip_ansi_codes(&err.to_string()),
+            "cargo:eza uses the cargo backend which is not allowed, allowed backends: core, asdf"
+        );
+        let planned = ts
+            .install_all_versions(&config, vec![tiny], &opts)
+            .await
+            .unwrap();
+        assert_eq!(
+            planned.iter().map(|tv| tv.to_string()).collect_vec(),
+            vec!["asdf:tiny@1.1.0"]
+        );
+    }
+
+    #[test]
+    fn test_backend_semaphores() {
+        let settings = Settings {
+            jobs_per_backend: vec![
+                "cargo=1".to_string(),
+                " ubi = 8 ".to_string(),
+                "aqua=0".to_string(),
+                "go".to_string(),
+            ],
+            ..Default::default()
+        };
+        let limits = settings.jobs_per_backend();
+        assert_eq!(
+            limits,
+            BTreeMap::from([("cargo".to_string(), 1), ("ubi".to_string(), 8)])
+        );
+        let semaphores =
+            backend_semaphores(["cargo", "ubi", "core", "cargo"].map(String::from), &limits);
+        assert_eq!(semaphores.len(), 2);
+        assert_eq!(semaphores["cargo"].available_permits(), 1);
+        assert_eq!(semaphores["ubi"].available_permits(), 8);
+        assert!(!semaphores.contains_key("core"));
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_rollback_on_failure() {
+        let installs = TestInstalls::new();
+        let tiny = |version: &str, opts: &str| installs.request("tiny", version, opts);
+        let config = Config::get().await;
+        let opts = InstallOptions {
+            rollback_on_failure: true,
+            ..Default::default()
+        };
+        Toolset::new(ToolSource::Argument)
+            .install_all_versions(&config, vec![tiny("1.0.0", "")], &opts)
+            .await
+            .unwrap();
+
+        // versions of one tool install last to first
+        let batch = vec![
+            tiny("3.0.1", ",verify_cmd=false"),
+            tiny("2.0.1", ""),
+            tiny("1.0.0", ""),
+        ];
+        let err = Toolset::new(ToolSource::Argument)
+            .install_all_versions(&config, batch, &opts)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("verify_cmd failed"), "{err:#}");
+        // installed before the batch so it is kept
+        assert!(installs.path().join("tiny/1.0.0/bin/rtx-tiny").exists());
+        // installed by the failed batch so it is removed
+        assert!(!installs.path().join("tiny/2.0.1").exists());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_verify_cmd() {
+        let config = Config::get().await;
+        let install = |verify_cmd: &str| {
+            let installs = TestInstalls::new();
+            let tr = installs.request("tiny", "1.0.1", &format!(",verify_cmd={verify_cmd}"));
+            let config = config.clone();
+            async move {
+                let res = Toolset::new(ToolSource::Argument)
+                    .install_all_versions(&config, vec![tr.clone()], &InstallOptions::default())
+                    .await;
+                (installs, tr, res)
+            }
+        };
+        let (installs, _, res) = install("rtx-tiny --version").await;
+        assert_eq!(res.unwrap().len(), 1);
+        assert!(installs.path().join("tiny/1.0.1/bin/rtx-tiny").exists());
+
+        let (installs, tr, res) = install("false").await;
+        let err = format!("{:#}", res.unwrap_err());
+        assert!(
+            err.starts_with("verify_cmd failed for asdf:tiny@1.0.1: false"),
+            "{err}"
+        );
+        let tv = tr.resolve(&config, &Default::default()).await.unwrap();
+        assert!(tv.install_path().starts_with(installs.path()));
+        assert!(
+            !tv.backend()
+                .unwrap()
+                .is_version_installed(&config, &tv, true)
+        );
+
+        // an existing install is not verified again, let alone removed
+        let installs = TestInstalls::new();
+        let opts = InstallOptions::default();
+        let installed = Toolset::new(ToolSource::Argument)
+            .install_all_versions(&config, vec![installs.request("tiny", "1.0.1", "")], &opts)
+            .await
+            .unwrap();
+        let tr = installs.request("tiny", "1.0.1", ",verify_cmd=false");
+        Toolset::new(ToolSource::Argument)
+            .install_all_versions(&config, vec![tr], &opts)
+            .await
+            .unwrap();
+        assert!(installs.path().join("tiny/1.0.1/bin/rtx-tiny").exists());
+        let ba = installed[0].backend().unwrap();
+        assert!(ba.is_version_installed(&config, &installed[0], true));
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_install_receipt() {
+        let installs = TestInstalls::new();
+        let tr = installs.request("tiny", "1.0.0", "");
+        let config = Config::get().await;
+        let install = |force: bool| {
+            let opts = InstallOptions {
+                force,
+                ..Default::default()
+            };
+            let (config, tr) = (config.clone(), tr.clone());
+            async move {
+                Toolset::new(ToolSource::Argument)
+                    .install_all_versions(&config, vec![tr], &opts)
+                    .await
+                    .unwrap()
+                    .remove(0)
+            }
+        };
+        let tv = install(false).await;
+        let receipt = || sbom::read_receipt(&tv.install_path()).unwrap().unwrap();
+        assert_eq!(receipt().entry.version, "1.0.0");
+
+        let url = Some("https://example.com/tiny-1.0.0.tar.gz".to_string());
+        let entry = sbom::SbomEntry {
+            url: url.clone(),
+            ..sbom::SbomEntry::from_install(&tv, None)
+        };
+        sbom::write_receipt(&tv, &entry).unwrap();
+        // already installed, so the receipt from the install that did the work is kept
+        install(false).await;
+        assert_eq!(receipt().entry.url, url);
+        install(true).await;
+        assert_eq!(receipt().entry.url, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_remote_versions() {
+        let installs = TestInstalls::new();
+        let ts = toolset_with(vec![installs.fake_install("tiny", "3.1.0", &[])]);
+        let versions = ts.list_remote_versions("tiny").await.unwrap();
+        assert_eq!(
+            versions,
+            vec![
+                "1.0.0", "1.1.0", "1.0.1", "2.0.0", "2.0.1", "2.1.0", "3.0.0", "3.0.1", "3.1.0"
+            ]
+        );
+        let err = ts.list_remote_versions("dummy").await.unwrap_err();
+        assert_eq!(err.to_string(), "dummy is not in the current toolset");
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_var() {
+        let installs = TestInstalls::new();
+        let tvs = ["4.3.0", "4.2.0"].map(|v| {
+            let tv = installs.fake_install("tiny", v, &[]);
+            file::write(tv.install_path().join("VERSION"), v).unwrap();
+            let _ = file::remove_all(tv.cache_path());
+            tv
+        });
+        let ts = toolset_with(tvs.to_vec());
+        let config = Config::get().await;
+        let v = ts.env_var(&config, "JDXCODE_TINY").await.unwrap();
+        assert_eq!(v.as_deref(), Some("4.3.0"));
+        // the first tool provided the var so the second one's exec-env never ran
+        let ran_exec_env = |tv: &ToolVersion| {
+            file::ls(&tv.cache_path())
+                .unwrap_or_default()
+                .iter()
+                .any(|p| {
+                    p.file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .starts_with("exec_env")
+                })
+        };
+        assert!(ran_exec_env(&tvs[0]));
+        assert!(!ran_exec_env(&tvs[1]));
+
+        let v = ts.env_var(&config, "TEST_ENV_VAR").await.unwrap();
+        assert_eq!(v.as_deref(), Some("test-123"));
+    }
+
+    #[tokio::test]
+    async fn test_install_options_semaphore() {
+        let opts = InstallOptions {
+            jobs: Some(2),
+            raw: false,
+            ..Default::default()
+        };
+        assert_eq!(opts.semaphore().available_permits(), 2);
+        let opts = InstallOptions {
+            semaphore: Some(opts.semaphore()),
+            ..opts
+        };
+        assert!(Arc::ptr_eq(&opts.semaphore(), &opts.semaphore()));
+
+        // a batch still holding permits limits the next one
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let mut jset = JoinSet::new();
+        for _batch in 0..2 {
+            for _ in 0..3 {
+                let semaphore = opts.semaphore();
+                let (running, max) = (running.clone(), max.clone());
+                jset.spawn(async move {
+                    let _permit = semaphore.acquire().await.unwrap();
+                    let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(n, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        }
+        jset.join_all().await;
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_with_path_for_tools() {
+        let installs = TestInstalls::new();
+        let tiny = installs.fake_install("tiny", "4.10.0", &["tiny-bin"]);
+        file::write(tiny.install_path().join("VERSION"), "4.10.0").unwrap();
+        let tiny_bin = tiny.install_path().join("bin");
+        let dummy = installs.fake_install("dummy", "4.10.0", &["dummy-bin"]);
+        let dummy_bin = dummy.install_path().join("bin");
+        let ts = toolset_with(vec![tiny, dummy]);
+        let config = Config::get().await;
+        let env = ts
+            .env_with_path_for_tools(&config, &["tiny".to_string()])
+            .await
+            .unwrap();
+        let paths = env::split_paths(&env[&*PATH_KEY]).collect_vec();
+        assert!(paths.contains(&tiny_bin), "{paths:?}");
+        assert!(!paths.contains(&dummy_bin), "{paths:?}");
+        assert_eq!(env["JDXCODE_TINY"], "4.10.0");
+        assert_eq!(env["TEST_ENV_VAR"], "test-123");
+
+        let err = ts
+            .env_with_path_for_tools(&config, &["node".to_string()])
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "node is not in the current toolset");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_orphaned_versions() {
+        let installs = TestInstalls::new();
+        let pinned = installs.fake_install("tiny", "4.13.0", &[]);
+        // orphans are found by listing the installs dir so this one can't go in a tempdir
+        let orphan = pinned.ba().installs_path.join("4.14.0");
+        file::create_dir_all(&orphan).unwrap();
+        let symlink = pinned.ba().installs_path.join("4.14");
+        let _ = file::remove_file(&symlink);
+        file::make_symlink(Path::new("./4.14.0"), &symlink).unwrap();
+        install_state::reset();
+        let ts = toolset_with(vec![pinned]);
+        let tiny_versions = |tvs: Vec<TVTuple>| {
+            tvs.into_iter()
+                .filter(|(p, _)| p.id() == "tiny")
+                .map(|(_, tv)| tv.version)
+                .collect_vec()
+        };
+        let orphans = tiny_versions(ts.orphaned_versions().unwrap());
+        assert!(orphans.contains(&"4.14.0".to_string()), "{orphans:?}");
+        assert!(!orphans.contains(&"4.13.0".to_string()), "{orphans:?}");
+        assert!(!orphans.contains(&"4.14".to_string()), "{orphans:?}");
+        let symlinks = tiny_versions(ts.orphaned_symlinks().unwrap());
+        assert!(symlinks.contains(&"4.14".to_string()), "{symlinks:?}");
+        file::remove_file(&symlink).unwrap();
+        file::remove_all(&orphan).unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_final_env_over() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.12.0", &["tiny-bin"]);
+        file::write(tv.install_path().join("VERSION"), "4.12.0").unwrap();
+        let bin = tv.install_path().join("bin");
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let base = EnvMap::from([
+            ("JDXCODE_TINY".to_string(), "from-base".to_string()),
+            ("ONLY_IN_BASE".to_string(), "1".to_string()),
+            (PATH_KEY.to_string(), "/base/bin".to_string()),
+        ]);
+        let env = ts.final_env_over(&config, base).await.unwrap();
+        assert_eq!(env["JDXCODE_TINY"], "4.12.0");
+        assert_eq!(env["ONLY_IN_BASE"], "1");
+        assert_eq!(env["TEST_ENV_VAR"], "test-123");
+        let paths = env::split_paths(&env[&*PATH_KEY]).collect_vec();
+        assert!(paths.contains(&bin), "{paths:?}");
+        assert_eq!(paths.last(), Some(&PathBuf::from("/base/bin")));
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_final_env_unset() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.20.0", &[]);
+        file::write(tv.install_path().join("VERSION"), ENV_UNSET).unwrap();
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let (env, env_results) = ts.final_env(&config).await.unwrap();
+        assert!(!env.contains_key("JDXCODE_TINY"));
+        assert!(env_results.env_remove.contains("JDXCODE_TINY"));
+
+        let base = EnvMap::from([
+            ("JDXCODE_TINY".to_string(), "inherited".to_string()),
+            ("ONLY_IN_BASE".to_string(), "1".to_string()),
+        ]);
+        let env = ts.final_env_over(&config, base).await.unwrap();
+        assert!(!env.contains_key("JDXCODE_TINY"));
+        assert_eq!(env["ONLY_IN_BASE"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_uv_venv_looked_up_once_per_env() {
+        let installs = TestInstalls::new();
+        let ts = toolset_with(vec![installs.fake_install("dummy", "1.0.0", &[])]);
+        let config = Config::get().await;
+        ts.env_with_path(&config).await.unwrap();
+        assert_eq!(ts.venv_lookups.load(Ordering::Relaxed), 1);
+        ts.full_env(&config).await.unwrap();
+        assert_eq!(ts.venv_lookups.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_no_path() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.18.0", &[]);
+        file::write(tv.install_path().join("VERSION"), "4.18.0").unwrap();
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let env = ts.env_no_path(&config).await.unwrap();
+        assert_eq!(env["JDXCODE_TINY"], "4.18.0");
+        assert!(!env.contains_key(&*PATH_KEY));
+        assert_eq!(ts.final_path_lookups.load(Ordering::Relaxed), 0);
+        ts.env_with_path(&config).await.unwrap();
+        assert_eq!(ts.final_path_lookups.load(Ordering::Relaxed), 1);
+    }
+
+    #[derive(Debug)]
+    struct LargeBackend {
+        ba: Arc<BackendArg>,
+    }
+
+    #[async_trait::async_trait]
+    impl Backend for LargeBackend {
+        fn ba(&self) -> &Arc<BackendArg> {
+            &self.ba
+        }
+
+        async fn _list_remote_versions(&self) -> Result<Vec<String>> {
+            Ok(vec!["1.0.0".into()])
+        }
+
+        async fn install_version_(
+            &self,
+            _ctx: &InstallContext,
+            _tv: ToolVersion,
+        ) -> Result<ToolVersion> {
+            panic!("the disk space check should have stopped the install");
+        }
+
+        async fn estimated_install_size(&self, _tv: &ToolVersion) -> Result<Option<u64>> {
+            Ok(Some(500 * 1024 * 1024))
+        }
+    }
+
+    #[derive(Debug)]
+    struct DefaultsBackend {
+        ba: Arc<BackendArg>,
+    }
+
+    #[async_trait::async_trait]
+    impl Backend for DefaultsBackend {
+        fn ba(&self) -> &Arc<BackendArg> {
+            &self.ba
+        }
+
+        async fn _list_remote_versions(&self) -> Result<Vec<String>> {
+            Ok(vec!["1.0.0".into()])
+        }
+
+        async fn install_version_(
+            &self,
+            _ctx: &InstallContext,
+            tv: ToolVersion,
+        ) -> Result<ToolVersion> {
+            Ok(tv)
+        }
+
+        fn default_options(&self) -> ToolVersionOptions {
+            parse_tool_options("matching=musl,exe=default")
+        }
+    }
+
+    #[test]
+    fn test_apply_default_options() {
+        let ba = Arc::new(BackendArg::new("defaults-test".into(), None));
+        let backend = DefaultsBackend { ba: ba.clone() };
+        let mut tr = ToolRequest::new(ba.clone(), "1.0.0", ToolSource::Argument).unwrap();
+        apply_default_options(&mut tr, &backend);
+        assert_eq!(tr.options().get("matching").unwrap(), "musl");
+        assert_eq!(tr.options().get("exe").unwrap(), "default");
+
+        let opts = parse_tool_options("exe=mine");
+        let mut tr = ToolRequest::new_opts(ba, "1.0.0", opts, ToolSource::Argument).unwrap();
+        apply_default_options(&mut tr, &backend);
+        assert_eq!(tr.options().get("matching").unwrap(), "musl");
+        assert_eq!(tr.options().get("exe").unwrap(), "mine");
+    }
+
+    #[tokio::test]
+    async fn test_ensure_disk_space() {
+        let ba = Arc::new(BackendArg::new("large-test".into(), None));
+        let backend = LargeBackend { ba: ba.clone() };
+        let tr = ToolRequest::new(ba, "1.0.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "1.0.0".into());
+
+        let err = ensure_disk_space(&backend, &tv, |_| Some(100 * 1024 * 1024))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "not enough disk space to install {tv}: it needs about 500MiB but {} only has 100MiB free",
+                display_path(tv.install_path())
+            )
+        );
+        ensure_disk_space(&backend, &tv, |_| Some(1024 * 1024 * 1024))
+            .await
+            .unwrap();
+        // unknown free space does not block installs
+        ensure_disk_space(&backend, &tv, |_| None).await.unwrap();
+    }
+
+    #[test]
+    fn test_path_dedupe() {
+        let paths = || {
+            ["/tool/bin", "/usr/bin", "/other/bin", "/usr/bin"]
+                .map(PathBuf::from)
+                .to_vec()
+        };
+        assert_eq!(PathDedupe::default(), PathDedupe::FirstWins);
+        assert_eq!(
+            PathDedupe::FirstWins.apply(paths()),
+            ["/tool/bin", "/usr/bin", "/other/bin"].map(PathBuf::from)
+        );
+        assert_eq!(
+            PathDedupe::LastWins.apply(paths()),
+            ["/tool/bin", "/other/bin", "/usr/bin"].map(PathBuf::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_join_bounded() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let results = join_bounded(0..6, 2, |i| {
+            let (running, max) = (running.clone(), max.clone());
+            async move {
+                let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(n, Ordering::SeqCst);
+                // later items finish first so ordering comes from the index, not completion
+                tokio::time::sleep(std::time::Duration::from_millis(20 - i * 3)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(i * 10)
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(results, vec![0, 10, 20, 30, 40, 50]);
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_install_options_mpr() {
+        let global = MultiProgressReport::get();
+        assert!(Arc::ptr_eq(&InstallOptions::default().mpr(), &global));
+
+        let a = InstallOptions {
+            mpr: Some(Arc::new(MultiProgressReport::new())),
+            ..Default::default()
+        };
+        let b = InstallOptions {
+            mpr: Some(Arc::new(MultiProgressReport::new())),
+            ..Default::default()
+        };
+        assert!(Arc::ptr_eq(&a.mpr(), &a.mpr()));
+        assert!(!Arc::ptr_eq(&a.mpr(), &b.mpr()));
+        assert!(!Arc::ptr_eq(&a.mpr(), &global));
+        assert!(!Arc::ptr_eq(&b.mpr(), &global));
+    }
+
+    #[tokio::test]
+    async fn test_tools_install_path_in_env_template() {
+        let installs = TestInstalls::new();
+        use crate::config::env_directive::{EnvDirective, EnvDirectiveOptions};
+
+        let tv = installs.fake_install("tiny", "4.9.0", &["tiny-bin"]);
+        let install_path = tv.install_path();
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let mut ctx = config.tera_ctx.clone();
+        ctx.insert("tools", &ts.tools_tera_ctx());
+        let opts = EnvDirectiveOptions {
+            tools: true,
+            ..Default::default()
+        };
+        let initial = EnvMap::new();
+        let resolve = |value: &str| {
+            let entry = EnvDirective::Val("TINY_HOME".into(), value.into(), opts.clone());
+            EnvResults::resolve(
+                &config,
+                ctx.clone(),
+                &initial,
+                vec![(entry, PathBuf::from("mise.toml"))],
+                EnvResolveOptions {
+                    tools: true,
+                    ..Default::default()
+                },
+            )
+        };
+        let env = resolve("{{ tools.tiny.install_path }}/lib").await.unwrap();
+        assert_eq!(
+            env.env["TINY_HOME"].0,
+            format!("{}/lib", install_path.display())
+        );
+
+        let err = resolve("{{ tools.java.install_path }}").await.unwrap_err();
+        assert!(
+            format!("{err:#}").contains("tools.java.install_path"),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_with_sources() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.5.0", &[]);
+        file::write(tv.install_path().join("VERSION"), "4.5.0").unwrap();
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let env = ts.env_with_sources(&config).await.unwrap();
+        let source = |key: &str| {
+            env.iter()
+                .find(|(k, _, _)| k == key)
+                .map(|(_, v, src)| (v.clone(), src.clone()))
+        };
+        assert_eq!(
+            source("JDXCODE_TINY"),
+            Some(("4.5.0".to_string(), PathBuf::from("tiny")))
+        );
+        let (v, src) = source("TEST_ENV_VAR").unwrap();
+        assert_eq!(v, "test-123");
+        assert!(src.ends_with("config/config.toml"), "{}", src.display());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_from_tools_strict() {
+        let installs = TestInstalls::new();
+        // tiny's exec-env fails without a VERSION file
+        let tv = installs.fake_install("tiny", "4.6.0", &[]);
+        let _ = file::remove_file(tv.install_path().join("VERSION"));
+        let _ = file::remove_all(tv.cache_path());
+        let ts = toolset_with(vec![tv]);
+        assert!(ts.env_from_tools_strict(false).await.unwrap().is_empty());
+        let err = ts.env_from_tools_strict(true).await.unwrap_err();
+        assert!(
+            format!("{err:#}").contains("failed to run exec-env for asdf:tiny@4.6.0"),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_from_tools_cached() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.17.0", &[]);
+        file::write(tv.install_path().join("VERSION"), "4.17.0").unwrap();
+        let ts = toolset_with(vec![tv]);
+        let config = Config::get().await;
+        let env = ts.env_from_tools().await;
+        assert!(
+            env.iter()
+                .any(|(k, v, _)| k == "JDXCODE_TINY" && v == "4.17.0")
+        );
+        ts.env_with_path(&config).await.unwrap();
+        ts.final_env(&config).await.unwrap();
+        assert_eq!(ts.env_from_tools().await, env);
+        assert_eq!(ts.tool_env.misses.load(Ordering::Relaxed), 1);
+        ts.tool_env.clear();
+        ts.env_from_tools().await;
+        assert_eq!(ts.tool_env.misses.load(Ordering::Relaxed), 2);
+
+        // the same version with other options is computed again
+        let mut other = ts.clone();
+        let tv = &mut other.versions[0].versions[0];
+        tv.request.set_options(parse_tool_options("foo=bar"));
+        other.env_from_tools().await;
+        assert_eq!(ts.tool_env.misses.load(Ordering::Relaxed), 3);
+        other.env_from_tools().await;
+        assert_eq!(ts.tool_env.misses.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_env_from_tools_filtered() {
+        let installs = TestInstalls::new();
+        let tv = installs.fake_install("tiny", "4.4.0", &[]);
+        file::write(tv.install_path().join("VERSION"), "4.4.0").unwrap();
+        let ts = toolset_with(vec![tv]);
+        let env = ts
+            .env_from_tools_filtered(&["JDXCODE_TINY".to_string(), "MISSING".to_string()])
+            .await;
+        assert_eq!(
+            env,
+            vec![(
+                "JDXCODE_TINY".to_string(),
+                "4.4.0".to_string(),
+                "tiny".to_string()
+            )]
+        );
+        assert!(ts.env_from_tools_filtered(&[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_join_until() {
+        let mut jset: JoinSet<Result<&str>> = JoinSet::new();
+        jset.spawn(async { Ok("fast") });
+        jset.spawn(async {
+            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            Ok("slow")
+        });
+        let deadline = Instant::now() + std::time::Duration::from_millis(100);
+        let results = join_until(jset, deadline).await.unwrap();
+        assert_eq!(results, vec!["fast"]);
+        assert!(Instant::now() < deadline + std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_list_current_versions_with_state() {
+        let ba: Arc<BackendArg> = Arc::new("tiny".into());
+        let mut ts = Toolset::new(ToolSource::Argument);
+        ts.add_version(ToolRequest::new(ba, "latest", ToolSource::Argument).unwrap());
+        let state = |ts: &Toolset| {
+            ts.list_current_versions_with_state()
+                .into_iter()
+                .map(|(_, tv, resolved)| (tv.version, resolved))
+                .collect_vec()
+        };
+        assert_eq!(state(&ts), vec![("latest".to_string(), false)]);
+        ts.resolve(false).await.unwrap();
+        let resolved = state(&ts);
+        assert_eq!(resolved.len(), 1);
+        assert_ne!(resolved[0].0, "latest");
+        assert!(resolved[0].1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reuse_resolved() {
+        let installs = TestInstalls::new();
+        let mut tiny = installs.fake_install("tiny", "4.8.0", &[]);
+        // not set by resolving, so it marks whether the version was resolved again
+        tiny.checksums.insert("marker".into(), "1".into());
+        let mut ts = toolset_with(vec![tiny]);
+        let dummy = ToolRequest::new(Arc::new("dummy".into()), "1.0.0", ToolSource::Argument);
+        ts.add_version(dummy.unwrap());
+        let resolved = |ts: &Toolset| {
+            ts.list_current_versions()
+                .into_iter()
+                .map(|(_, tv)| (tv.ba().short.clone(), !tv.checksums.is_empty()))
+                .collect_vec()
+        };
+        assert_eq!(resolved(&ts), vec![("tiny".into(), true)]);
+
+        ts.resolve(true).await.unwrap();
+        assert_eq!(
+            resolved(&ts),
+            vec![("tiny".into(), true), ("dummy".into(), false)]
+        );
+
+        ts.resolve(false).await.unwrap();
+        assert_eq!(
+            resolved(&ts),
+            vec![("tiny".into(), false), ("dummy".into(), false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_install_retries() {
+        let backend = backend::get(&"tiny".into()).unwrap();
+        let tv = || {
+            let tr = ToolRequest::new(backend.ba().clone(), "1.0.0", ToolSource::Argument);
+            ToolVersion::new(tr.unwrap(), "1.0.0".into())
+        };
+        let retry = (3, Duration::ZERO);
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        let res = with_install_retries(&*backend, retry, |_| async {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(eyre::eyre!("checksum mismatch"))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        let res = with_install_retries(&*backend, retry, |attempt| {
+            assert_eq!(
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+                attempt
+            );
+            async move {
+                match attempt {
+                    0 | 1 => Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into()),
+                    _ => Ok(tv()),
+                }
+            }
+        })
+        .await;
+        assert_eq!(res.unwrap().version, "1.0.0");
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_with_install_timeout() {
+        let backend = backend::get(&"tiny".into()).unwrap();
+        let tr = ToolRequest::new(backend.ba().clone(), "1.0.0", ToolSource::Argument).unwrap();
+        let tv = ToolVersion::new(tr, "1.0.0".into());
+        let semaphore = Arc::new(Semaphore::new(1));
+        let mut jset = JoinSet::new();
+        for hang in [true, false] {
+            let (backend, tv, semaphore) = (backend.clone(), tv.clone(), semaphore.clone());
+            jset.spawn(async move {
+                let _permit = semaphore.acquire().await?;
+                let install = async {
+                    if hang {
+                        std::future::pending::<()>().await;
+                    }
+                    Ok(tv.clone())
+                };
+                let timeout = Some(Duration::from_millis(20));
+                with_install_timeout(&*backend, &tv, timeout, install)
+                    .await
+                    .wrap_err_with(|| format!("failed to install {tv}"))
+            });
+        }
+        let results = jset.join_all().await;
+        let errors = results
+            .iter()
+            .filter_map(|r| r.as_ref().err().map(|e| format!("{e:#}")))
+            .collect_vec();
+        assert_eq!(
+            errors,
+            vec!["failed to install asdf:tiny@1.0.0: timed out after 20ms"]
+        );
+        // the hung install gave up its permit so the other one could run
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_install_retries_backoff() {
+        let backend = backend::get(&"tiny".into()).unwrap();
+        let start = Instant::now();
+        let err = with_install_retries(&*backend, (2, Duration::from_millis(20)), |_| async {
+            Err(
+                eyre::Report::from(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+                    .wrap_err("download failed"),
+            )
+        })
+        .await
+        .unwrap_err();
+        // 20ms then 40ms
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        // the original error chain is kept
+        assert_eq!(
+            err.chain().map(|e| e.to_string()).collect_vec(),
+            vec!["download failed", "connection reset"]
+        );
+    }
+
+    #[test]
+    fn test_clear_disabled() {
+        let mut ts = Toolset::new(ToolSource::Argument);
+        for short in ["tiny", "clear-disabled-test"] {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            ts.add_version(ToolRequest::new(ba, "1.0.0", ToolSource::Argument).unwrap());
+        }
+        let mut settings = SettingsPartial::empty();
+        settings.disable_tools = Some(["clear-disabled-test".to_string()].into());
+        Settings::reset(Some(settings));
+        let removed = ts.clear_disabled();
+        Settings::reset(None);
+        assert_eq!(removed, vec!["clear-disabled-test".to_string()]);
+        assert_eq!(
+            ts.versions.keys().map(|ba| ba.short.clone()).collect_vec(),
+            vec!["tiny".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_remove_version() {
+        let mut ts = Toolset::new(ToolSource::Argument);
+        for short in ["tiny", "dummy", "remove-disabled-test"] {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            ts.add_version(ToolRequest::new(ba, "1.0.0", ToolSource::Argument).unwrap());
+        }
+        let tvl = ts.remove_version(&"tiny".into()).unwrap();
+        assert_eq!(tvl.requests.len(), 1);
+        assert!(ts.remove_version(&"tiny".into()).is_none());
+        let mut settings = SettingsPartial::empty();
+        settings.disable_tools = Some(["remove-disabled-test".to_string()].into());
+        Settings::reset(Some(settings));
+        let disabled = ts.remove_version(&"remove-disabled-test".into());
+        Settings::reset(None);
+        assert!(disabled.is_none());
+        assert_eq!(
+            ts.versions.keys().map(|ba| ba.short.clone()).collect_vec(),
+            vec!["dummy".to_string(), "remove-disabled-test".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let tv = |short: &str, version: &str| {
+            let ba: Arc<BackendArg> = Arc::new(short.into());
+            let tr = ToolRequest::new(ba, version, ToolSource::Argument).unwrap();
+            ToolVersion::new(tr, version.into())
+        };
+        let shorts = |bas: &[Arc<BackendArg>]| bas.iter().map(|ba| ba.short.clone()).collect_vec();
+
+        let old = toolset_with(vec![tv("tiny", "1.0.0"), tv("dummy", "2.0.0")]);
+        let new = toolset_with(vec![
+            tv("tiny", "1.1.0"),
+            tv("dummy", "2.0.0"),
+            tv("node", "22.0.0"),
+        ]);
+        let diff = old.diff(&new);
+        assert_eq!(shorts(&diff.added), vec!["node"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        let (ba, from, to) = &diff.changed[0];
+        assert_eq!(ba.short, "tiny");
+        assert_eq!(from, &vec!["1.0.0".to_string()]);
+        assert_eq!(to, &vec!["1.1.0".to_string()]);
+        assert!(old.diff(&old).is_empty());
+
+        let disjoint = toolset_with(vec![tv("node", "22.0.0")]);
+        let diff = old.diff(&disjoint);
+        assert_eq!(shorts(&diff.added), vec!["node"]);
+        assert_eq!(shorts(&diff.removed), vec!["tiny", "dummy"]);
+        assert!(diff.changed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_env_for_dir() {
+        let installs = TestInstalls::new();
+        let tmp = tempfile::tempdir().unwrap();
+        let tv = installs.fake_install("tiny", "4.8.0", &[]);
+        let ts = toolset_with(vec![tv.clone()]);
+        let config = Config::get().await;
+        // each call stands in for a chpwd hook within the same project
+        for _ in 0..3 {
+            let env = ts.env_for_dir(&config, tmp.path()).await.unwrap();
+            assert_eq!(env.get("TEST_ENV_VAR").unwrap(), "test-123");
+        }
+        assert_eq!(ts.final_path_lookups.load(Ordering::Relaxed), 1);
+
+        // reinstalling a tool invalidates the env
+        let set_modified = |path: &Path, t| {
+            let f = std::fs::File::open(path).unwrap();
+            f.set_modified(t).unwrap();
+        };
+        let later = std::time::SystemTime::now() + Duration::from_secs(60);
+        set_modified(&tv.install_path(), later);
+        ts.env_for_dir(&config, tmp.path()).await.unwrap();
+        assert_eq!(ts.final_path_lookups.load(Ordering::Relaxed), 2);
+
+        // and so does editing a config file
+        let cf = tmp.path().join("mise.toml");
+        file::write(&cf, "[env]\nFOO = 'bar'\n").unwrap();
+        let key = dir_env_key([&cf], std::slice::from_ref(&tv));
+        assert_eq!(dir_env_key([&cf], std::slice::from_ref(&tv)), key);
+        set_modified(&cf, later);
+        assert_ne!(dir_env_key([&cf], &[tv]), key);
     }
 }
@@ -1,3 +1,4 @@
+use crate::plugins::VERSION_REGEX;
 use crate::toolset;
 use crate::toolset::{ToolRequest, ToolSource, ToolVersion};
 use crate::{Result, config::Config};
@@ -56,7 +57,24 @@ impl OutdatedInfo {
             .find(&tv.request.version())
             .map(|m| m.as_str().to_string());
         let latest_result = if bump {
-            t.latest_version(prefix.clone()).await
+            // prereleases are only suggested as a bump with `prerelease = true`
+            let prerelease = tv
+                .request
+                .options()
+                .get("prerelease")
+                .is_some_and(|v| v == "true");
+            let p = prefix.clone().unwrap_or_default();
+            match t.latest_version(prefix.clone()).await {
+                Ok(Some(latest))
+                    if !prerelease && is_prerelease(latest.strip_prefix(&p).unwrap_or(&latest)) =>
+                {
+                    t.list_remote_versions().await.map(|versions| {
+                        let versions = versions.into_iter().filter(|v| !t.is_version_yanked(v));
+                        latest_stable(versions, &p)
+                    })
+                }
+                res => res,
+            }
         } else {
             tv.latest_version(config).await.map(Option::from)
         };
@@ -224,6 +242,25 @@ fn chunkify_version(v: &str) -> Vec<String> {
     chunks
 }
 
+/// whether `v` has a prerelease part like "1.2.0-rc.1"
+fn is_prerelease(v: &str) -> bool {
+    let v = v.strip_prefix('v').unwrap_or(v);
+    match Versioning::new(v) {
+        Some(Versioning::Ideal(sv)) => sv.pre_rel.is_some() || VERSION_REGEX.is_match(v),
+        _ => VERSION_REGEX.is_match(v),
+    }
+}
+
+/// the last version starting with `prefix` that is not a prerelease
+fn latest_stable(
+    versions: impl DoubleEndedIterator<Item = String>,
+    prefix: &str,
+) -> Option<String> {
+    versions
+        .rev()
+        .find(|v| v.strip_prefix(prefix).is_some_and(|v| !is_prerelease(v)))
+}
+
 pub fn is_outdated_version(current: &str, latest: &str) -> bool {
     if let (Some(c), Some(l)) = (Version::new(current), Version::new(latest)) {
         c.lt(&l)
@@ -237,7 +274,7 @@ mod tests {
     use pretty_assertions::assert_eq;
     use test_log::test;
 
-    use super::{check_semver_bump, is_outdated_version};
+    use super::{check_semver_bump, is_outdated_version, is_prerelease, latest_stable};
 
     #[test]
     fn test_is_outdated_version() {
@@ -302,4 +339,22 @@ mod tests {
             Some("latest".to_string())
         );
     }
+
+    #[test]
+    fn test_bump_skips_prerelease() {
+        assert!(is_prerelease("1.2.0-rc.1"));
+        assert!(is_prerelease("v2.0.0-canary.3"));
+        assert!(is_prerelease("3.13.0a1"));
+        assert!(!is_prerelease("1.1.0"));
+        assert!(!is_prerelease("2024-10-21"));
+        let versions = ["1.0.0", "1.1.0", "1.2.0-rc.1"].map(String::from);
+        let latest = latest_stable(versions.into_iter(), "").unwrap();
+        assert_eq!(latest, "1.1.0");
+        assert_eq!(check_semver_bump("1.0", &latest), Some("1.1".to_string()));
+        let versions = ["temurin-21.0.1", "temurin-22.0.0-beta", "zulu-23.0.0"].map(String::from);
+        assert_eq!(
+            latest_stable(versions.into_iter(), "temurin-"),
+            Some("temurin-21.0.1".to_string())
+        );
+    }
 }
@@ -9,6 +9,7 @@ use versions::{Chunk, Version};
 use xx::file;
 
 use crate::cli::args::BackendArg;
+use crate::config::SETTINGS;
 use crate::lockfile::LockfileTool;
 use crate::runtime_symlinks::is_runtime_symlink;
 use crate::toolset::tool_version::ResolveOptions;
@@ -21,71 +22,73 @@ pub enum ToolRequest {
     Version {
         backend: Arc<BackendArg>,
         version: String,
-        options: ToolVersionOptions,
+        options: Option<ToolVersionOptions>,
         source: ToolSource,
     },
     Prefix {
         backend: Arc<BackendArg>,
         prefix: String,
-        options: ToolVersionOptions,
+        options: Option<ToolVersionOptions>,
         source: ToolSource,
     },
     Ref {
         backend: Arc<BackendArg>,
         ref_: String,
         ref_type: String,
-        options: ToolVersionOptions,
+        options: Option<ToolVersionOptions>,
         source: ToolSource,
     },
     Sub {
         backend: Arc<BackendArg>,
         sub: String,
         orig_version: String,
-        options: ToolVersionOptions,
+        options: Option<ToolVersionOptions>,
         source: ToolSource,
     },
     Path {
         backend: Arc<BackendArg>,
         path: PathBuf,
-        options: ToolVersionOptions,
+        options: Option<ToolVersionOptions>,
         source: ToolSource,
     },
     System {
         backend: Arc<BackendArg>,
         source: ToolSource,
-        options: ToolVersionOptions,
+        options: Option<ToolVersionOptions>,
     },
 }
 
 impl ToolRequest {
     pub fn new(backend: Arc<BackendArg>, s: &str, source: ToolSource) -> eyre::Result<Self> {
         let s = match s.split_once('-') {
-            Some((ref_type @ ("ref" | "tag" | "branch" | "rev"), r)) => format!("{ref_type}:{r}"),
+            Some((ref_type, r)) if parse_ref_type(ref_type).is_some() => {
+                format!("{}:{r}", parse_ref_type(ref_type).unwrap())
+            }
             _ => s.to_string(),
         };
         Ok(match s.split_once(':') {
-            Some((ref_type @ ("ref" | "tag" | "branch" | "rev"), r)) => Self::Ref {
+            Some((ref_type, r)) if parse_ref_type(ref_type).is_some() => Self::Ref {
                 ref_: r.to_string(),
-                ref_type: ref_type.to_string(),
-                options: backend.opts(),
+                ref_type: parse_ref_type(ref_type).unwrap().to_string(),
+                options: backend.explicit_opts(),
                 backend,
                 source,
             },
             Some(("prefix", p)) => Self::Prefix {
                 prefix: p.to_string(),
-                options: backend.opts(),
+                options: backend.explicit_opts(),
                 backend,
                 source,
             },
             Some(("path", p)) => Self::Path {
                 path: PathBuf::from(p),
-                options: backend.opts(),
+                options: backend.explicit_opts(),
                 backend,
                 source,
             },
             Some((p, v)) if p.starts_with("sub-") => Self::Sub {
                 sub: p.split_once('-').unwrap().1.to_string(),
-                options: backend.opts(),
+                options: backend.explicit_opts(),
                 orig_version: v.to_string(),
                 backend,
                 source,
@@ -93,14 +96,14 @@ impl ToolRequest {
             None => {
                 if s == "system" {
                     Self::System {
-                        options: backend.opts(),
+                        options: backend.explicit_opts(),
                         backend,
                         source,
                     }
                 } else {
                     Self::Version {
                         version: s,
-                        options: backend.opts(),
+                        options: backend.explicit_opts(),
                         backend,
                         source,
                     }
@@ -119,7 +122,7 @@ impl ToolRequest {
         match &mut tvr {
             Self::Version { options: o, .. }
             | Self::Prefix { options: o, .. }
-            | Self::Ref { options: o, .. } => *o = options,
+            | Self::Ref { options: o, .. } => *o = Some(options),
             _ => Default::default(),
         }
         Ok(tvr)
@@ -165,9 +168,17 @@ impl ToolRequest {
             | Self::Ref { options, .. }
             | Self::Path { options, .. }
             | Self::Sub { options, .. }
-            | Self::System { options, .. } => &options.os,
+            | Self::System { options, .. } => options.as_ref().map_or(&None, |o| &o.os),
         }
     }
+    /// the `install_path` option, which is only honored when it is absolute
+    pub fn install_path_option(&self) -> Option<PathBuf> {
+        self.options()
+            .get("install_path")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+    }
+
     pub fn set_options(&mut self, options: ToolVersionOptions) -> &mut Self {
         match self {
             Self::Version { options: o, .. }
@@ -175,7 +186,7 @@ impl ToolRequest {
             | Self::Ref { options: o, .. }
             | Self::Sub { options: o, .. }
             | Self::Path { options: o, .. }
-            | Self::System { options: o, .. } => *o = options,
+            | Self::System { options: o, .. } => *o = Some(options),
         }
         self
     }
@@ -201,7 +212,18 @@ impl ToolRequest {
             | Self::Ref { options: o, .. }
             | Self::Sub { options: o, .. }
             | Self::Path { options: o, .. }
-            | Self::System { options: o, .. } => o.clone(),
+            | Self::System { options: o, .. } => o.clone().unwrap_or_default(),
+        }
+    }
+    /// the options if any were given, `Some` even when they were explicitly emptied with `tool[]`
+    pub fn explicit_options(&self) -> Option<&ToolVersionOptions> {
+        match self {
+            Self::Version { options: o, .. }
+            | Self::Prefix { options: o, .. }
+            | Self::Ref { options: o, .. }
+            | Self::Sub { options: o, .. }
+            | Self::Path { options: o, .. }
+            | Self::System { options: o, .. } => o.as_ref(),
         }
     }
 
@@ -289,7 +311,7 @@ impl ToolRequest {
 
     pub fn is_os_supported(&self) -> bool {
         if let Some(os) = self.os() {
-            if !os.contains(&crate::cli::version::OS) {
+            if !os_matches(os, &crate::cli::version::OS, SETTINGS.arch()) {
                 return false;
             }
         }
@@ -297,6 +319,25 @@ impl ToolRequest {
     }
 }
 
+/// whether any of the `os` option's tokens match the given os and arch. a token is either
+/// `<os>`, e.g. `linux`, which matches any arch, or `<os>-<arch>`, e.g. `linux-aarch64`.
+/// arch accepts rust's names (`x86_64`, `aarch64`) as well as `x64`/`amd64` and `arm64`.
+fn os_matches(tokens: &[String], os: &str, arch: &str) -> bool {
+    let arch = normalize_arch(arch);
+    tokens.iter().any(|t| match t.split_once('-') {
+        Some((t_os, t_arch)) => t_os == os && normalize_arch(t_arch) == arch,
+        None => t == os,
+    })
+}
+
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "x64" | "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        arch => arch,
+    }
+}
+
 /// subtracts sub from orig and removes suffix
 /// e.g. version_sub("18.2.3", "2") -> "16"
 /// e.g. version_sub("18.2.3", "0.1") -> "18.1"
@@ -319,15 +360,63 @@ impl Display for ToolRequest {
     }
 }
 
+/// the normalized ref type if `s` is one of ref/tag/branch/rev in any case, e.g.: `Tag` -> `tag`
+pub fn parse_ref_type(s: &str) -> Option<&'static str> {
+    ["ref", "tag", "branch", "rev"]
+        .into_iter()
+        .find(|rt| rt.eq_ignore_ascii_case(s))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::version_sub;
+    use std::sync::Arc;
+
+    use super::*;
     use pretty_assertions::assert_str_eq;
     use test_log::test;
 
+    #[test]
+    fn test_os_matches() {
+        let tokens = |t: &[&str]| t.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let linux = tokens(&["linux"]);
+        assert!(os_matches(&linux, "linux", "x86_64"));
+        assert!(os_matches(&linux, "linux", "aarch64"));
+        assert!(!os_matches(&linux, "macos", "aarch64"));
+
+        let linux_arm = tokens(&["linux-aarch64"]);
+        assert!(os_matches(&linux_arm, "linux", "aarch64"));
+        assert!(!os_matches(&linux_arm, "linux", "x86_64"));
+        assert!(!os_matches(&linux_arm, "macos", "aarch64"));
+
+        let aliases = tokens(&["linux-arm64", "macos-x64"]);
+        assert!(os_matches(&aliases, "linux", "aarch64"));
+        assert!(os_matches(&aliases, "macos", "x86_64"));
+        assert!(os_matches(&aliases, "macos", "amd64"));
+        assert!(!os_matches(&aliases, "linux", "x86_64"));
+
+        let mixed = tokens(&["macos", "linux-x86_64"]);
+        assert!(os_matches(&mixed, "macos", "aarch64"));
+        assert!(os_matches(&mixed, "linux", "x86_64"));
+        assert!(!os_matches(&mixed, "linux", "aarch64"));
+        assert!(!os_matches(&mixed, "windows", "x86_64"));
+        assert!(!os_matches(&[], "linux", "x86_64"));
+    }
+
     #[test]
     fn test_version_sub() {
         assert_str_eq!(version_sub("18.2.3", "2"), "16");
         assert_str_eq!(version_sub("18.2.3", "0.1"), "18.1");
     }
+
+    #[test]
+    fn test_ref_type_case_insensitive() {
+        let ba: Arc<BackendArg> = Arc::new("tiny".into());
+        let lower = ToolRequest::new(ba.clone(), "tag:v1", ToolSource::Argument).unwrap();
+        let upper = ToolRequest::new(ba.clone(), "Tag:v1", ToolSource::Argument).unwrap();
+        assert_eq!(lower, upper);
+        assert_str_eq!(upper.version(), "tag:v1");
+        let dashed = ToolRequest::new(ba, "BRANCH-main", ToolSource::Argument).unwrap();
+        assert!(matches!(dashed, ToolRequest::Ref { ref ref_type, .. } if ref_type == "branch"));
+        assert_eq!(parse_ref_type("prefix"), None);
+    }
 }
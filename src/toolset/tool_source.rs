@@ -12,6 +12,8 @@ pub enum ToolSource {
     ToolVersions(PathBuf),
     MiseToml(PathBuf),
     IdiomaticVersionFile(PathBuf),
+    /// a standalone tool spec, see Toolset::from_spec_file
+    SpecFile(PathBuf),
     Argument,
     Environment(String, String),
     #[default]
@@ -24,6 +26,7 @@ impl Display for ToolSource {
             ToolSource::ToolVersions(path) => write!(f, "{}", display_path(path)),
             ToolSource::MiseToml(path) => write!(f, "{}", display_path(path)),
             ToolSource::IdiomaticVersionFile(path) => write!(f, "{}", display_path(path)),
+            ToolSource::SpecFile(path) => write!(f, "{}", display_path(path)),
             ToolSource::Argument => write!(f, "--runtime"),
             ToolSource::Environment(k, v) => write!(f, "{k}={v}"),
             ToolSource::Unknown => write!(f, "unknown"),
@@ -37,6 +40,7 @@ impl ToolSource {
             ToolSource::ToolVersions(path) => Some(path),
             ToolSource::MiseToml(path) => Some(path),
             ToolSource::IdiomaticVersionFile(path) => Some(path),
+            ToolSource::SpecFile(path) => Some(path),
             _ => None,
         }
     }
@@ -55,6 +59,10 @@ impl ToolSource {
                 "type".to_string() => "idiomatic-version-file".to_string(),
                 "path".to_string() => path.to_string_lossy().to_string(),
             },
+            ToolSource::SpecFile(path) => indexmap! {
+                "type".to_string() => "spec-file".to_string(),
+                "path".to_string() => path.to_string_lossy().to_string(),
+            },
             ToolSource::Argument => indexmap! {
                 "type".to_string() => "argument".to_string(),
             },
@@ -89,6 +97,10 @@ impl Serialize for ToolSource {
                 s.serialize_field("type", "idiomatic-version-file")?;
                 s.serialize_field("path", path)?;
             }
+            ToolSource::SpecFile(path) => {
+                s.serialize_field("type", "spec-file")?;
+                s.serialize_field("path", path)?;
+            }
             ToolSource::Argument => {
                 s.serialize_field("type", "argument")?;
             }
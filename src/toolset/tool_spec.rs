@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use eyre::{Result, WrapErr};
+use indexmap::IndexMap;
+use serde_derive::Deserialize;
+
+use crate::cli::args::BackendArg;
+use crate::file;
+use crate::file::display_path;
+use crate::toolset::{ToolRequest, ToolSource, ToolVersionOptions, Toolset};
+
+/// a standalone list of tools to provision, independent of any project config, e.g.:
+///
+/// ```toml
+/// [tools.node]
+/// version = ["22", "20"]
+///
+/// [tools."cargo:eza"]
+/// version = "latest"
+/// os = ["linux", "macos"]
+/// options = { features = "git" }
+/// install_env = { CARGO_PROFILE_RELEASE_LTO = "false" }
+/// ```
+///
+/// the same structure can be written as yaml in a `.yaml`/`.yml` file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolSpec {
+    #[serde(default)]
+    tools: IndexMap<String, ToolSpecEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolSpecEntry {
+    version: ToolSpecVersions,
+    os: Option<Vec<String>>,
+    #[serde(default)]
+    options: BTreeMap<String, String>,
+    #[serde(default)]
+    install_env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ToolSpecVersions {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ToolSpecVersions {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(v) => vec![v],
+            Self::Many(v) => v,
+        }
+    }
+}
+
+impl Toolset {
+    /// reads the tools in a spec file (see ToolSpec) into an unresolved toolset, for bootstrapping
+    /// a machine without a mise.toml. `install_missing_versions` then provisions them.
+    /// unknown keys are an error so a typo doesn't silently skip a tool or option.
+    pub fn from_spec_file(path: &Path) -> Result<Toolset> {
+        let body = file::read_to_string(path)?;
+        let spec: Result<ToolSpec> = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&body).map_err(Into::into),
+            _ => toml::from_str(&body).map_err(Into::into),
+        };
+        let spec =
+            spec.wrap_err_with(|| format!("failed to parse tool spec {}", display_path(path)))?;
+        let source = ToolSource::SpecFile(path.to_path_buf());
+        let mut ts = Toolset::new(source.clone());
+        for (name, entry) in spec.tools {
+            let ba: Arc<BackendArg> = Arc::new(name.as_str().into());
+            let options = ToolVersionOptions {
+                os: entry.os,
+                install_env: entry.install_env,
+                opts: entry.options,
+            };
+            for version in entry.version.into_vec() {
+                let mut tr = ToolRequest::new(ba.clone(), &version, source.clone())?;
+                tr.set_options(options.clone());
+                ts.add_version(tr);
+            }
+        }
+        Ok(ts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn requests(ts: &Toolset) -> Vec<(String, String, ToolVersionOptions)> {
+        ts.versions
+            .values()
+            .flat_map(|tvl| &tvl.requests)
+            .map(|tr| (tr.ba().short.clone(), tr.version(), tr.options()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_spec_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("tools.toml");
+        file::write(
+            &path,
+            r#"
+[tools.tiny]
+version = ["2", "1.1.0"]
+
+[tools.dummy]
+version = "latest"
+os = ["linux", "macos"]
+options = { foo = "bar" }
+install_env = { DUMMY_FLAG = "1" }
+"#,
+        )
+        .unwrap();
+        let ts = Toolset::from_spec_file(&path).unwrap();
+        assert_eq!(ts.source, Some(ToolSource::SpecFile(path.clone())));
+        let dummy = ToolVersionOptions {
+            os: Some(vec!["linux".into(), "macos".into()]),
+            install_env: [("DUMMY_FLAG".to_string(), "1".to_string())].into(),
+            opts: [("foo".to_string(), "bar".to_string())].into(),
+        };
+        let expected = vec![
+            ("tiny".to_string(), "2".to_string(), Default::default()),
+            ("tiny".to_string(), "1.1.0".to_string(), Default::default()),
+            ("dummy".to_string(), "latest".to_string(), dummy),
+        ];
+        assert_eq!(requests(&ts), expected);
+
+        let yaml = tmp.path().join("tools.yaml");
+        file::write(
+            &yaml,
+            r#"
+tools:
+  tiny:
+    version: ["2", "1.1.0"]
+  dummy:
+    version: latest
+    os: [linux, macos]
+    options: { foo: bar }
+    install_env: { DUMMY_FLAG: "1" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(requests(&Toolset::from_spec_file(&yaml).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_from_spec_file_unknown_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("tools.toml");
+        file::write(
+            &path,
+            "[tools.tiny]\nversion = \"2\"\nos_filter = [\"linux\"]\n",
+        )
+        .unwrap();
+        let err = Toolset::from_spec_file(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown field `os_filter`"));
+
+        file::write(&path, "[tool.tiny]\nversion = \"2\"\n").unwrap();
+        let err = Toolset::from_spec_file(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown field `tool`"));
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 use std::fs;
 use std::hash::{Hash, Hasher};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{cmp::Ordering, sync::LazyLock};
 
 use crate::backend::ABackend;
@@ -11,10 +11,11 @@ use crate::config::Config;
 #[cfg(windows)]
 use crate::file;
 use crate::hash::hash_to_str;
+use crate::toolset::tool_request::parse_ref_type;
 use crate::toolset::{ToolRequest, ToolVersionOptions, tool_request};
 use console::style;
 use dashmap::DashMap;
-use eyre::Result;
+use eyre::{Result, bail, ensure, eyre};
 #[cfg(windows)]
 use path_absolutize::Absolutize;
 
@@ -43,6 +44,13 @@ impl ToolVersion {
         opts: &ResolveOptions,
     ) -> Result<Self> {
         trace!("resolving {} {}", &request, opts);
+        if let Some(p) = request.options().get("install_path") {
+            ensure!(
+                Path::new(p).is_absolute(),
+                "install_path for {} must be an absolute path: {p}",
+                request.ba()
+            );
+        }
         if opts.use_locked_version {
             if let Some(lt) = request.lockfile_resolve(config)? {
                 let mut tv = Self::new(request.clone(), lt.version);
@@ -76,6 +84,17 @@ impl ToolVersion {
         Ok(tv)
     }
 
+    /// false if this is still a placeholder such as "latest" which has not been resolved to a
+    /// concrete version yet
+    pub fn is_resolved(&self) -> bool {
+        match &self.request {
+            ToolRequest::Version { .. } => self.version != "latest",
+            ToolRequest::Prefix { .. } => !self.version.starts_with("prefix:"),
+            ToolRequest::Sub { .. } => !self.version.starts_with("sub-"),
+            _ => true,
+        }
+    }
+
     pub fn ba(&self) -> &BackendArg {
         self.request.ba()
     }
@@ -92,6 +111,9 @@ impl ToolVersion {
         if let Some(p) = &self.install_path {
             return p.clone();
         }
+        if let Some(p) = self.request.install_path_option() {
+            return p;
+        }
         static CACHE: LazyLock<DashMap<ToolVersion, PathBuf>> = LazyLock::new(DashMap::new);
         if let Some(p) = CACHE.get(self) {
             return p.clone();
@@ -133,7 +155,9 @@ impl ToolVersion {
         let tv = self.request.resolve(config, &opts).await?;
         // map cargo backend specific prefixes to ref
         let version = match tv.request.version().split_once(':') {
-            Some((_ref_type @ ("tag" | "branch" | "rev"), r)) => {
+            Some((ref_type, r))
+                if matches!(parse_ref_type(ref_type), Some("tag" | "branch" | "rev")) =>
+            {
                 format!("ref:{r}")
             }
             _ => tv.version,
@@ -173,10 +197,10 @@ impl ToolVersion {
         let backend = request.backend()?;
         let v = config.resolve_alias(&backend, v).await?;
         match v.split_once(':') {
-            Some((ref_type @ ("ref" | "tag" | "branch" | "rev"), r)) => {
+            Some((ref_type, r)) if parse_ref_type(ref_type).is_some() => {
                 return Ok(Self::resolve_ref(
                     r.to_string(),
-                    ref_type.to_string(),
+                    parse_ref_type(ref_type).unwrap().to_string(),
                     request.options(),
                     &request,
                 ));
@@ -212,6 +236,15 @@ impl ToolVersion {
                 return build(v);
             }
         }
+        if v == "prev" {
+            // installed versions are sorted so the last one is the newest
+            let current = backend
+                .list_installed_versions()?
+                .pop()
+                .ok_or_else(|| eyre!("no installed version of {} to go back from", backend.id()))?;
+            let remote = backend.list_remote_versions().await?;
+            return build(previous_version(&remote, &current)?);
+        }
         if !opts.latest_versions {
             let matches = backend.list_installed_versions_matching(&v)?;
             if matches.contains(&v) {
@@ -275,7 +308,7 @@ impl ToolVersion {
             backend: tr.ba().clone(),
             ref_,
             ref_type,
-            options: opts.clone(),
+            options: Some(opts.clone()),
             source: tr.source().clone(),
         };
         let version = request.version();
@@ -288,13 +321,22 @@ impl ToolVersion {
             backend: tr.ba().clone(),
             path,
             source: tr.source().clone(),
-            options: tr.options().clone(),
+            options: tr.explicit_options().cloned(),
         };
         let version = request.version();
         Ok(Self::new(request, version))
     }
 }
 
+/// the version sorted immediately before `current` in `versions`, used for `prev`
+fn previous_version(versions: &[String], current: &str) -> Result<String> {
+    match versions.iter().position(|v| v == current) {
+        Some(0) => bail!("no version before {current}"),
+        Some(i) => Ok(versions[i - 1].clone()),
+        None => bail!("{current} is not in the list of remote versions"),
+    }
+}
+
 impl Display for ToolVersion {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}@{}", &self.ba().full(), &self.version)
@@ -358,3 +400,47 @@ impl Display for ResolveOptions {
         write!(f, "({})", opts.join(", "))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::file;
+    use crate::toolset::{ToolSource, install_state};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_previous_version() {
+        let versions = ["1.0.0", "1.1.0", "2.0.0"].map(String::from);
+        assert_eq!(previous_version(&versions, "2.0.0").unwrap(), "1.1.0");
+        assert_eq!(previous_version(&versions, "1.1.0").unwrap(), "1.0.0");
+        assert!(previous_version(&versions, "1.0.0").is_err());
+        assert!(previous_version(&versions, "3.0.0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_prev() {
+        let config = Config::get().await;
+        let ba: Arc<BackendArg> = Arc::new("dummy".into());
+        let tr = ToolRequest::new(ba.clone(), "prev", ToolSource::Argument).unwrap();
+        let opts = ResolveOptions::default();
+        let err = ToolVersion::resolve(&config, tr.clone(), &opts)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with("no installed version of"),
+            "{err}"
+        );
+
+        // dummy's remote versions are 1.0.0, 1.1.0, and 2.0.0
+        for v in ["1.0.0", "2.0.0"] {
+            file::create_dir_all(ba.installs_path.join(v)).unwrap();
+        }
+        install_state::reset();
+        let tv = ToolVersion::resolve(&config, tr, &opts).await;
+        file::remove_all(&ba.installs_path).unwrap();
+        install_state::reset();
+        assert_eq!(tv.unwrap().version, "1.1.0");
+    }
+}
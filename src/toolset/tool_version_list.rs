@@ -41,6 +41,17 @@ impl ToolVersionList {
         }
         Ok(())
     }
+
+    /// every request is an exact version that has already been resolved, so resolving again
+    /// would give the same versions
+    pub fn is_concretely_resolved(&self) -> bool {
+        self.versions.len() == self.requests.len()
+            && self.requests.iter().zip(&self.versions).all(|(tr, tv)| {
+                matches!(tr, ToolRequest::Version { .. })
+                    && tv.request == *tr
+                    && tv.version == tr.version()
+            })
+    }
 }
 
 #[cfg(test)]
@@ -32,7 +32,9 @@ impl MultiProgressReport {
         *mutex = Some(Arc::downgrade(&mpr));
         mpr
     }
-    fn new() -> Self {
+    /// a report that is not shared with the global instance so separate install batches keep
+    /// their progress bars apart
+    pub fn new() -> Self {
         let settings = Settings::get();
         let mp = match settings.raw
             || settings.quiet
@@ -78,6 +80,12 @@ impl MultiProgressReport {
     }
 }
 
+impl Default for MultiProgressReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
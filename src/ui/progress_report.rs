@@ -1,7 +1,7 @@
 #![allow(unknown_lints)]
 #![allow(clippy::literal_string_with_formatting_args)]
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use indicatif::{ProgressBar, ProgressStyle};
@@ -180,12 +180,73 @@ impl SingleReport for VerboseReport {
     }
 }
 
+/// forwards to `inner` and records each message, used to keep a tool's install log
+pub struct CaptureReport {
+    inner: Box<dyn SingleReport>,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl CaptureReport {
+    pub fn new(inner: Box<dyn SingleReport>, log: Arc<Mutex<Vec<String>>>) -> CaptureReport {
+        CaptureReport { inner, log }
+    }
+
+    fn record(&self, message: &str) {
+        self.log.lock().unwrap().push(message.to_string());
+    }
+}
+
+impl SingleReport for CaptureReport {
+    fn println(&self, message: String) {
+        self.record(&message);
+        self.inner.println(message);
+    }
+    fn set_message(&self, message: String) {
+        self.record(&message);
+        self.inner.set_message(message);
+    }
+    fn inc(&self, delta: u64) {
+        self.inner.inc(delta);
+    }
+    fn set_position(&self, delta: u64) {
+        self.inner.set_position(delta);
+    }
+    fn set_length(&self, length: u64) {
+        self.inner.set_length(length);
+    }
+    fn abandon(&self) {
+        self.inner.abandon();
+    }
+    fn finish(&self) {
+        self.inner.finish();
+    }
+    fn finish_with_message(&self, message: String) {
+        self.inner.finish_with_message(message);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use test_log::test;
 
     use super::*;
 
+    #[test]
+    fn test_capture_report() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let pr: Box<dyn SingleReport> = Box::new(CaptureReport::new(
+            Box::new(QuietReport::new()),
+            log.clone(),
+        ));
+        pr.set_message("bin/install".into());
+        crate::cmd::CmdLineRunner::new("echo")
+            .arg("hello")
+            .with_pr(&pr)
+            .execute()
+            .unwrap();
+        assert_eq!(*log.lock().unwrap(), vec!["bin/install", "hello"]);
+    }
+
     #[test]
     fn test_progress_report() {
         let pr = ProgressReport::new("foo".into());
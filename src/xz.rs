@@ -0,0 +1,270 @@
+//! multi-threaded decompression of xz files made of several blocks, as `xz -T` and other
+//! multi-threaded compressors write them. blocks are independent so each one is decoded on a
+//! worker thread by wrapping it in a stream of its own, and the output is returned in order.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+
+use eyre::{Result, bail, ensure};
+
+const HEADER_SIZE: u64 = 12;
+const FOOTER_SIZE: u64 = 12;
+const HEADER_MAGIC: &[u8] = &[0xFD, b'7', b'z', b'X', b'Z', 0];
+const FOOTER_MAGIC: &[u8] = b"YZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    offset: u64,
+    /// size of the block without its padding, as recorded in the index
+    unpadded_size: u64,
+    uncompressed_size: u64,
+}
+
+/// the blocks of a file holding a single xz stream
+#[derive(Debug)]
+pub struct Index {
+    header: [u8; HEADER_SIZE as usize],
+    blocks: Vec<Block>,
+}
+
+impl Index {
+    /// reads the index at the end of `f`. fails for anything but a single stream without
+    /// stream padding, those are left to the single-threaded decoder
+    pub fn read(f: &mut File) -> Result<Self> {
+        let size = f.seek(SeekFrom::End(0))?;
+        ensure!(
+            size >= HEADER_SIZE + FOOTER_SIZE,
+            "too small to be an xz file"
+        );
+        let mut header = [0; HEADER_SIZE as usize];
+        f.seek(SeekFrom::Start(0))?;
+        f.read_exact(&mut header)?;
+        ensure!(header.starts_with(HEADER_MAGIC), "not an xz file");
+        let mut footer = [0; FOOTER_SIZE as usize];
+        f.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+        f.read_exact(&mut footer)?;
+        ensure!(
+            footer.ends_with(FOOTER_MAGIC),
+            "no xz stream footer at the end"
+        );
+        ensure!(footer[8..10] == header[6..8], "xz stream flags don't match");
+        let index_size = (u64::from(u32::from_le_bytes(footer[4..8].try_into()?)) + 1) * 4;
+        ensure!(
+            index_size <= size - HEADER_SIZE - FOOTER_SIZE,
+            "invalid xz index size"
+        );
+        let index_offset = size - FOOTER_SIZE - index_size;
+        let mut index = vec![0; index_size as usize];
+        f.seek(SeekFrom::Start(index_offset))?;
+        f.read_exact(&mut index)?;
+        let (index, crc) = index.split_at(index.len() - 4);
+        ensure!(
+            crc32fast::hash(index) == u32::from_le_bytes(crc.try_into()?),
+            "xz index is corrupt"
+        );
+        let Some((&0, mut records)) = index.split_first() else {
+            bail!("invalid xz index");
+        };
+        let count = read_varint(&mut records)?;
+        let mut blocks = vec![];
+        let mut offset = HEADER_SIZE;
+        for _ in 0..count {
+            let unpadded_size = read_varint(&mut records)?;
+            let uncompressed_size = read_varint(&mut records)?;
+            blocks.push(Block {
+                offset,
+                unpadded_size,
+                uncompressed_size,
+            });
+            offset += padded(unpadded_size);
+        }
+        // the index only covers the last stream when several are concatenated
+        ensure!(offset == index_offset, "xz file holds more than one stream");
+        Ok(Self { header, blocks })
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+}
+
+type Job = (Block, Sender<io::Result<Vec<u8>>>);
+
+/// decodes the blocks of an xz file on up to `jobs` threads. only `jobs` decoded blocks are
+/// held in memory at once.
+pub struct MtDecoder {
+    jobs: Sender<Job>,
+    pending: VecDeque<Receiver<io::Result<Vec<u8>>>>,
+    blocks: std::vec::IntoIter<Block>,
+    buf: Cursor<Vec<u8>>,
+}
+
+impl MtDecoder {
+    pub fn spawn(f: File, index: Index, jobs: usize) -> Result<Self> {
+        let f = Arc::new(Mutex::new(f));
+        let header = index.header;
+        let (tx, rx) = channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let jobs = jobs.clamp(1, index.len().max(1));
+        for i in 0..jobs {
+            let (f, rx) = (f.clone(), rx.clone());
+            std::thread::Builder::new()
+                .name(format!("xz-decoder-{i}"))
+                .spawn(move || {
+                    // workers stop once the decoder, and with it the job sender, is dropped
+                    loop {
+                        let Ok((block, out)) = rx.lock().unwrap().recv() else {
+                            break;
+                        };
+                        let res = read_block(&f, block)
+                            .and_then(|data| decode_block(&header, &data, block));
+                        let _ = out.send(res);
+                    }
+                })?;
+        }
+        let mut decoder = Self {
+            jobs: tx,
+            pending: Default::default(),
+            blocks: index.blocks.into_iter(),
+            buf: Default::default(),
+        };
+        for _ in 0..jobs {
+            decoder.queue_next();
+        }
+        Ok(decoder)
+    }
+
+    fn queue_next(&mut self) {
+        if let Some(block) = self.blocks.next() {
+            let (tx, rx) = channel();
+            // a send only fails when every worker is gone, which `read` reports
+            let _ = self.jobs.send((block, tx));
+            self.pending.push_back(rx);
+        }
+    }
+}
+
+impl Read for MtDecoder {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.buf.read(out)?;
+            if n > 0 || out.is_empty() {
+                return Ok(n);
+            }
+            let Some(rx) = self.pending.pop_front() else {
+                return Ok(0);
+            };
+            let data = rx
+                .recv()
+                .map_err(|_| io::Error::other("xz decoder thread exited"))??;
+            self.buf = Cursor::new(data);
+            self.queue_next();
+        }
+    }
+}
+
+fn read_block(f: &Mutex<File>, block: Block) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; padded(block.unpadded_size) as usize];
+    let mut f = f.lock().unwrap();
+    f.seek(SeekFrom::Start(block.offset))?;
+    f.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// decodes `data`, one padded block, as a stream with the original header and an index and
+/// footer for just that block so liblzma still verifies sizes and checks
+fn decode_block(header: &[u8], data: &[u8], block: Block) -> io::Result<Vec<u8>> {
+    let mut index = vec![0, 1];
+    write_varint(&mut index, block.unpadded_size);
+    write_varint(&mut index, block.uncompressed_size);
+    index.resize(padded(index.len() as u64) as usize, 0);
+    index.extend(crc32fast::hash(&index).to_le_bytes());
+    let mut footer = ((index.len() / 4 - 1) as u32).to_le_bytes().to_vec();
+    footer.extend(&header[6..8]);
+    let footer = [
+        &crc32fast::hash(&footer).to_le_bytes()[..],
+        &footer,
+        FOOTER_MAGIC,
+    ]
+    .concat();
+    let stream = header.chain(data).chain(&index[..]).chain(&footer[..]);
+    // the size comes from the file so it only sizes the initial allocation up to a limit
+    let mut out = Vec::with_capacity(block.uncompressed_size.min(64 * 1024 * 1024) as usize);
+    xz2::read::XzDecoder::new(stream).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn padded(n: u64) -> u64 {
+    n.div_ceil(4) * 4
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut n = 0;
+    for i in 0..9 {
+        let Some((b, rest)) = buf.split_first() else {
+            bail!("truncated xz index");
+        };
+        *buf = rest;
+        n |= u64::from(b & 0x7F) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    bail!("invalid xz index")
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn compress(data: &[u8], block_size: u64) -> Vec<u8> {
+        let stream = xz2::stream::MtStreamBuilder::new()
+            .threads(2)
+            .block_size(block_size)
+            .encoder()
+            .unwrap();
+        let mut w = xz2::write::XzEncoder::new_stream(vec![], stream);
+        w.write_all(data).unwrap();
+        w.finish().unwrap()
+    }
+
+    #[test]
+    fn test_mt_decoder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let data = (0..1024 * 1024u32)
+            .map(|n| (n.wrapping_mul(2654435761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        let path = tmp.path().join("data.xz");
+        std::fs::write(&path, compress(&data, 64 * 1024)).unwrap();
+        let mut f = File::open(&path).unwrap();
+        let index = Index::read(&mut f).unwrap();
+        assert_eq!(index.len(), 16);
+        let mut out = vec![];
+        MtDecoder::spawn(f, index, 4)
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert!(out == data, "decoded data differs");
+
+        // concatenated streams are left to the single-threaded decoder
+        let concatenated = [compress(&data, 64 * 1024), compress(b"more", 64 * 1024)].concat();
+        std::fs::write(&path, concatenated).unwrap();
+        let err = Index::read(&mut File::open(&path).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "xz file holds more than one stream");
+    }
+}
@@ -3310,6 +3310,12 @@ const completionSpec: Fig.Spec = {
           description: "Show the version instead of the path",
           isRepeatable: false,
         },
+        {
+          name: ["-a", "--all"],
+          description:
+            "Show every active tool that provides the bin, not just the one that wins\nThe first one listed is the one that is used.",
+          isRepeatable: false,
+        },
         {
           name: ["-t", "--tool"],
           description: